@@ -0,0 +1,378 @@
+pub mod aperture;
+pub mod thin_lens;
+pub mod orthographic;
+pub mod fisheye;
+pub mod equirectangular;
+pub mod realistic;
+pub mod stereo;
+
+use crate::{point::Point, ray::Ray, sampler::Sampler, transform::Transform};
+use aperture::Aperture;
+use thin_lens::{ThinLens, Movements};
+use orthographic::Orthographic;
+use fisheye::{Fisheye, Mapping};
+use equirectangular::Equirectangular;
+use realistic::{Realistic, LensElement};
+
+//Settings of a film camera, the light of the scene is in units where the sunny 16 rule
+//(f/16 and a shutter of 1/ISO second in the sun) gives the image as it is computed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub iso : f64, //Sensitivity of the film
+    pub shutter : f64, //Time the shutter stays open, in seconds
+    pub f_number : f64, //Focal length over the diameter of the aperture, the depth of field follows it with the brightness
+}
+
+impl Default for Exposure {
+    fn default() -> Exposure {
+        Exposure {
+            iso : 100.,
+            shutter : 1./100.,
+            f_number : 16.,
+        }
+    }
+}
+
+impl Exposure {
+    pub fn new(iso : f64, shutter : f64, f_number : f64) -> Exposure {
+        Exposure { iso, shutter, f_number }
+    }
+
+    //The light reaching the film grows with the time and the area of the aperture
+    pub fn scale(&self) -> f64 {
+        self.iso*self.shutter*256./(self.f_number*self.f_number)
+    }
+
+    //Exposure value at ISO 100, each step halves the light
+    pub fn ev100(&self) -> f64 {
+        (self.f_number*self.f_number/self.shutter*100./self.iso).log2()
+    }
+}
+
+//Where the camera is and how it is turned, left handed as right = up ^ forward
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub origin : Point<f64>,
+    pub right : Point<f64>,
+    pub up : Point<f64>,
+    pub forward : Point<f64>,
+}
+
+impl View {
+    //Looking at lookat with the up vector at the top of the image, then turned by roll degrees around the
+    //look direction, the right side going up
+    //Looking along the up vector or at the origin itself still gives a valid view
+    pub fn look_at(origin : Point<f64>, lookat : Point<f64>, up : Point<f64>, roll : f64) -> View {
+        let forward = lookat - origin;
+        let forward = if forward.near_zero() { Point { x: 0., y: 0., z: -1. } } else { forward.unit() };
+        View::from_directions(origin, forward, up, roll)
+    }
+
+    //From a camera to world transform, the camera looking toward +z with y up and x on its right (as pbrt)
+    //The scale and the shear of the matrix are dropped
+    pub fn from_matrix(camera_to_world : &Transform) -> View {
+        let forward = camera_to_world.vector(Point { x: 0., y: 0., z: 1. });
+        let forward = if forward.near_zero() { Point { x: 0., y: 0., z: 1. } } else { forward.unit() };
+        View::from_directions(camera_to_world.point(Point { x: 0., y: 0., z: 0. }), forward, camera_to_world.vector(Point { x: 0., y: 1., z: 0. }), 0.)
+    }
+
+    fn from_directions(origin : Point<f64>, forward : Point<f64>, up : Point<f64>, roll : f64) -> View {
+        let mut up = up + forward*(-(up&forward));
+        if up.norm() < 1e-9 || up.norm().is_nan() {
+            //Any direction at right angle with the look one, from the axis the most across it
+            let axis = if forward.x.abs() <= forward.y.abs() && forward.x.abs() <= forward.z.abs() {
+                Point { x: 1., y: 0., z: 0. }
+            } else if forward.y.abs() <= forward.z.abs() {
+                Point { x: 0., y: 1., z: 0. }
+            } else {
+                Point { x: 0., y: 0., z: 1. }
+            };
+            up = axis + forward*(-(axis&forward));
+        }
+        let up = up.unit();
+        let right = up^forward;
+
+        let (sin, cos) = roll.to_radians().sin_cos();
+        View {
+            origin,
+            right : right*cos + up*sin,
+            up : up*cos - right*sin,
+            forward,
+        }
+    }
+
+    //The same view for a right handed scene (as glTF), right = forward ^ up so the scene isn't seen mirrored
+    pub fn right_handed(self) -> View {
+        View { right : self.right*-1., ..self }
+    }
+
+    fn point(&self, p : Point<f64>) -> Point<f64> {
+        self.origin + self.vector(p)
+    }
+
+    fn vector(&self, v : Point<f64>) -> Point<f64> {
+        self.right*v.x + self.up*v.y + self.forward*v.z
+    }
+}
+
+//How a camera model turns a point of the film into a ray
+pub trait Projection {
+    //x and y are in pixels from the center of the first pixel, lens is a point of the aperture in the unit disk
+    //The ray is in the space of the camera : x on the right, y up and looking toward +z
+    //None where the model sees nothing, as outside the circle of a fisheye
+    fn ray(&self, x : f64, y : f64, lens : (f64, f64)) -> Option<Ray>;
+}
+
+#[derive(Debug, Clone)]
+pub enum Model {
+    ThinLens(ThinLens),
+    Orthographic(Orthographic),
+    Fisheye(Fisheye),
+    Equirectangular(Equirectangular),
+    Realistic(Realistic),
+}
+
+impl Model {
+    pub fn as_projection(&self) -> &dyn Projection {
+        match self {
+            Model::ThinLens(thin_lens) => thin_lens,
+            Model::Orthographic(orthographic) => orthographic,
+            Model::Fisheye(fisheye) => fisheye,
+            Model::Equirectangular(equirectangular) => equirectangular,
+            Model::Realistic(realistic) => realistic,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    model : Model,
+    film_shift : f64, //Horizontal move of the image in pixels, set for an eye of an off-axis stereo rig
+    pub view : View,
+    pub aperture : Aperture,
+
+    pub image_height : u32,
+    pub image_width : u32,
+    pub exposure : Exposure,
+}
+
+impl Camera {
+    //Perspective camera with a thin lens focused at focus_dist, vfov in degrees
+    //The aperture is the diameter of the lens at f/16, the f-number of the exposure opens or closes it
+    pub fn new(origin : Point<f64>, lookat : Point<f64>, image_width : u32, aspect_ratio : f64, vfov : f64, aperture : f64, focus_dist : f64) -> Camera {
+        Camera::tilt_shift(origin, lookat, image_width, aspect_ratio, vfov, aperture, focus_dist, Movements::default())
+    }
+
+    //Same with the lens shifted and the plane of focus tilted, as on a view camera
+    #[allow(clippy::too_many_arguments)]
+    pub fn tilt_shift(origin : Point<f64>, lookat : Point<f64>, image_width : u32, aspect_ratio : f64, vfov : f64, aperture : f64, focus_dist : f64, movements : Movements) -> Camera {
+        let image_height = image_height(image_width, aspect_ratio);
+        let thin_lens = ThinLens::new(image_width, image_height, vfov, aperture, focus_dist, movements);
+        Camera::with_model(Model::ThinLens(thin_lens), View::look_at(origin, lookat, Point { x: 0., y: 1., z: 0. }, 0.), image_width, image_height)
+    }
+
+    //Parallel rays, view_height is the height of the image in the world
+    pub fn orthographic(origin : Point<f64>, lookat : Point<f64>, image_width : u32, aspect_ratio : f64, view_height : f64) -> Camera {
+        let image_height = image_height(image_width, aspect_ratio);
+        let orthographic = Orthographic::new(image_width, image_height, view_height);
+        Camera::with_model(Model::Orthographic(orthographic), View::look_at(origin, lookat, Point { x: 0., y: 1., z: 0. }, 0.), image_width, image_height)
+    }
+
+    //fov in degrees is the angle across the shorter side of the image
+    pub fn fisheye(origin : Point<f64>, lookat : Point<f64>, image_width : u32, aspect_ratio : f64, fov : f64, mapping : Mapping) -> Camera {
+        let image_height = image_height(image_width, aspect_ratio);
+        let fisheye = Fisheye::new(image_width, image_height, fov, mapping);
+        Camera::with_model(Model::Fisheye(fisheye), View::look_at(origin, lookat, Point { x: 0., y: 1., z: 0. }, 0.), image_width, image_height)
+    }
+
+    //All the directions around, the image is 2:1
+    //With an interpupillary distance, the left eye is on the top half and the right one below, in a square image
+    pub fn equirectangular(origin : Point<f64>, lookat : Point<f64>, image_width : u32, interpupillary : Option<f64>) -> Camera {
+        let image_height = if interpupillary.is_some() { image_width } else { image_width.div_ceil(2) };
+        let equirectangular = Equirectangular::new(image_width, image_height, interpupillary);
+        //The horizon stays level, whatever the look direction
+        let up = Point { x: 0., y: 1., z: 0. };
+        let lookat = lookat + up*(-((lookat - origin)&up));
+        Camera::with_model(Model::Equirectangular(equirectangular), View::look_at(origin, lookat, up, 0.), image_width, image_height)
+    }
+
+    //Through the glass elements of a lens, from the front to the film, the sizes of the lens and of the
+    //film diagonal are in millimeters with the scene in meters
+    //At f/16 the rays cover the rear element, a lower f-number spreads them wider for the stop to cut
+    pub fn realistic(origin : Point<f64>, lookat : Point<f64>, image_width : u32, aspect_ratio : f64, elements : Vec<LensElement>, film_diagonal : f64, focus_dist : f64) -> Camera {
+        let image_height = image_height(image_width, aspect_ratio);
+        let realistic = Realistic::new(elements, image_width, image_height, film_diagonal, focus_dist);
+        Camera::with_model(Model::Realistic(realistic), View::look_at(origin, lookat, Point { x: 0., y: 1., z: 0. }, 0.), image_width, image_height)
+    }
+
+    fn with_model(model : Model, view : View, image_width : u32, image_height : u32) -> Camera {
+        Camera {
+            model,
+            film_shift : 0.,
+            view,
+            aperture : Aperture::default(),
+            image_height,
+            image_width,
+            exposure : Exposure::default(),
+        }
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    pub fn pixel_ray(&self, i : u32, j : u32, sampler : &mut Sampler) -> Option<Ray> {
+        //Antialiasing random on the position of the pixel center
+        let (alpha, beta) = sampler.get_2d();
+        self.film_ray(i as f64 + alpha - 0.5, j as f64 + beta - 0.5, sampler)
+    }
+
+    //Ray through a point of the image, in pixels from the center of the first one
+    pub fn film_ray(&self, x : f64, y : f64, sampler : &mut Sampler) -> Option<Ray> {
+        let lens = sampler.get_2d();
+        //Somewhere while the shutter is open
+        let time = sampler.get_1d()*self.exposure.shutter;
+        //Only the models with a lens have an aperture, its barrel doesn't block the rays of the others
+        let lens = match self.model {
+            Model::ThinLens(_) | Model::Realistic(_) => {
+                //Position on the film, 1 in the corners
+                let (width, height) = (self.image_width as f64, self.image_height as f64);
+                let half_diagonal = (width*width + height*height).sqrt()/2.;
+                let film = ((x + 0.5 - width/2.)/half_diagonal, (height/2. - y - 0.5)/half_diagonal);
+                let lens = self.aperture.sample(lens, film)?;
+                //The diameter of the aperture is the focal length over the f-number, the models are built at f/16
+                let open = Exposure::default().f_number/self.exposure.f_number;
+                (lens.0*open, lens.1*open)
+            },
+            _ => lens,
+        };
+        let ray = self.model.as_projection().ray(x + self.film_shift, y, lens)?;
+        Some(Ray::new(self.view.point(*ray.orig()), self.view.vector(*ray.dir()), 0).at_time(time))
+    }
+}
+
+//Truncated, 100 pixels wide at 3:2 are 66 high
+fn image_height(image_width : u32, aspect_ratio : f64) -> u32 {
+    ((image_width as f64)/aspect_ratio) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    fn direction(camera : &Camera, x : f64, y : f64) -> Option<Point<f64>> {
+        let mut sampler = Sampler::new(SamplerKind::Independent, 0, 0, 0, 0, 1);
+        camera.film_ray(x, y, &mut sampler).map(|ray| ray.dir().unit())
+    }
+
+    #[test]
+    fn projections() {
+        let (origin, lookat) = (Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. });
+        let mut sampler = Sampler::new(SamplerKind::Independent, 0, 0, 0, 0, 1);
+        assert_eq!(Camera::new(origin, lookat, 100, 1.5, 90., 0., 1.).image_height, 66);
+
+        //Parallel rays from a plane of the given height, no lens barrel cuts the corners
+        let mut orthographic = Camera::orthographic(origin, lookat, 200, 2., 4.);
+        orthographic.aperture.cat_eye = 1.;
+        assert!((0..16).all(|sample| orthographic.film_ray(-0.5, -0.5, &mut Sampler::new(SamplerKind::Independent, 0, 0, 0, sample, 16)).is_some()));
+        let (top, bottom) = (orthographic.film_ray(0., -0.5, &mut sampler).unwrap(), orthographic.film_ray(0., 99.5, &mut sampler).unwrap());
+        assert!((top.orig().y - 2.).abs() < 1e-9 && (bottom.orig().y + 2.).abs() < 1e-9);
+        assert!((top.dir().z + 1.).abs() < 1e-9 && (bottom.dir().z + 1.).abs() < 1e-9);
+
+        //Half the field of view at the edge of the circle, the equidistant one sees behind in the corners
+        let fisheye = Camera::fisheye(origin, lookat, 200, 2., 180., fisheye::Mapping::Equidistant);
+        assert!((direction(&fisheye, 99.5, 49.5).unwrap().z + 1.).abs() < 1e-9);
+        assert!(direction(&fisheye, 49.5, 49.5).unwrap().z.abs() < 1e-9);
+        assert!(direction(&fisheye, 19.5, -0.5).unwrap().z > 0. && direction(&fisheye, -0.5, -0.5).is_none());
+        let equisolid = Camera::fisheye(origin, lookat, 200, 2., 180., fisheye::Mapping::Equisolid);
+        assert!(direction(&equisolid, 99.5, -0.5).unwrap().y > 0.99);
+        assert!(direction(&equisolid, -0.5, -0.5).is_none());
+        let all_around = Camera::fisheye(origin, lookat, 100, 1., 360., fisheye::Mapping::Equidistant);
+        assert!(direction(&all_around, -0.5, 49.5).unwrap().z > 0.99 && direction(&all_around, -0.5, -0.5).is_none());
+
+        //Forward in the center, backward on the sides, up at the top
+        let panorama = Camera::equirectangular(origin, lookat, 200, None);
+        assert_eq!(panorama.image_height, 100);
+        assert!((direction(&panorama, 99.5, 49.5).unwrap().z + 1.).abs() < 1e-9);
+        assert!((direction(&panorama, -0.5, 49.5).unwrap().z - 1.).abs() < 1e-9);
+        assert!((direction(&panorama, 49.5, 49.5).unwrap().x.abs() - 1.).abs() < 1e-9);
+        assert!((direction(&panorama, 99.5, -0.5).unwrap().y - 1.).abs() < 1e-9);
+
+        //The eyes are apart across the direction looked at, the left one on top
+        let stereo = Camera::equirectangular(origin, lookat, 200, Some(0.064));
+        assert_eq!(stereo.image_height, 200);
+        let (left, right) = (stereo.film_ray(99.5, 49.5, &mut sampler).unwrap(), stereo.film_ray(99.5, 149.5, &mut sampler).unwrap());
+        assert!(((*left.orig() - *right.orig()).norm() - 0.064).abs() < 1e-9 && (left.orig().x + right.orig().x).abs() < 1e-9);
+        assert!((*left.dir() - *right.dir()).near_zero() && left.orig().z.abs() < 1e-9);
+        let side = stereo.film_ray(49.5, 49.5, &mut sampler).unwrap();
+        assert!(side.orig().x.abs() < 1e-9 && (side.orig().z.abs() - 0.032).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tilt_and_shift() {
+        let (origin, lookat) = (Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: 1. });
+        let sample = |camera : &Camera, x, y, sample| camera.film_ray(x, y, &mut Sampler::new(SamplerKind::Independent, 0, 0, 0, sample, 1)).unwrap();
+
+        //Shifted up by a quarter of the image, a column still sees a vertical line of the world
+        let shift = Movements { shift : (0., 0.25), ..Movements::default() };
+        let camera = Camera::tilt_shift(origin, lookat, 40, 1., 60., 0., 2., shift);
+        let (top, bottom) = (sample(&camera, 5., 0., 0), sample(&camera, 5., 39., 0));
+        assert!((top.dir().x/top.dir().z - bottom.dir().x/bottom.dir().z).abs() < 1e-12);
+        let center = sample(&camera, 19.5, 19.5, 0);
+        assert!((center.dir().y/center.dir().z - 0.5*(30f64).to_radians().tan()).abs() < 1e-12);
+
+        //All the rays of a pixel meet on the tilted plane of focus
+        let tilt = Movements { tilt : 30., ..Movements::default() };
+        let camera = Camera::tilt_shift(origin, lookat, 40, 1., 60., 0.5, 2., tilt);
+        for (x, y) in [(19.5, 19.5), (3., 2.), (30., 37.)] {
+            let focus = sample(&camera, x, y, 0).at(1.);
+            assert!((focus.z - 2. - focus.y*(30f64).to_radians().tan()).abs() < 1e-9);
+            for k in 1..4 {
+                let ray = sample(&camera, x, y, k);
+                assert!((ray.at(1.) - focus).near_zero() && !(*ray.orig()).near_zero());
+            }
+        }
+
+        //Two stops down the lens is half as wide, the rays still meet at the focus
+        let mut camera = Camera::new(origin, lookat, 40, 1., 60., 0.5, 2.);
+        let wide = sample(&camera, 19.5, 19.5, 1);
+        camera.exposure.f_number = 32.;
+        let narrow = sample(&camera, 19.5, 19.5, 1);
+        assert!((narrow.orig().norm()*2. - wide.orig().norm()).abs() < 1e-12 && (narrow.at(1.) - wide.at(1.)).near_zero());
+    }
+
+    #[test]
+    fn views() {
+        let origin = Point { x: 1., y: 2., z: 3. };
+        let y = Point { x: 0., y: 1., z: 0. };
+        let finite = |view : &View| [view.right, view.up, view.forward].iter().all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite());
+
+        //Straight up or down, or at itself, the basis is still orthonormal
+        for lookat in [origin + y, origin - y, origin] {
+            let view = View::look_at(origin, lookat, y, 0.);
+            assert!(finite(&view));
+            assert!((view.right&view.up).abs() < 1e-12 && (view.up&view.forward).abs() < 1e-12 && ((view.up^view.forward) - view.right).near_zero());
+            assert!((view.right.norm() - 1.).abs() < 1e-12 && (view.up.norm() - 1.).abs() < 1e-12);
+        }
+        let camera = Camera::new(origin, origin - y, 40, 1., 60., 0., 1.);
+        assert!(direction(&camera, 19.5, 19.5).unwrap().y < -0.999);
+
+        //Another up vector, then a roll of a quarter turn
+        let view = View::look_at(origin, origin + Point { x: 0., y: 0., z: -1. }, Point { x: 1., y: 0., z: 0. }, 0.);
+        assert!((view.up.x - 1.).abs() < 1e-12);
+        let rolled = View::look_at(origin, origin + Point { x: 0., y: 0., z: -1. }, y, 90.);
+        let level = View::look_at(origin, origin + Point { x: 0., y: 0., z: -1. }, y, 0.);
+        assert!((rolled.right - level.up).near_zero() && (rolled.up + level.right).near_zero());
+
+        //From a camera to world matrix, scaled and turned to look along -x
+        let transform = Transform::translate(origin)*Transform::rotate(-90., y)*Transform::scale(Point { x: 2., y: 2., z: 2. });
+        let view = View::from_matrix(&transform);
+        assert!((view.origin - origin).near_zero() && (view.forward.x + 1.).abs() < 1e-12 && (view.up.y - 1.).abs() < 1e-12);
+        let mut camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: 1. }, 40, 1., 60., 0., 1.);
+        camera.view = view;
+        let mut sampler = Sampler::new(SamplerKind::Independent, 0, 0, 0, 0, 1);
+        let ray = camera.film_ray(19.5, 19.5, &mut sampler).unwrap();
+        assert!((*ray.orig() - origin).near_zero() && (ray.dir().unit().x + 1.).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{f64::consts::{PI, SQRT_2}, sync::Arc};
+
+use crate::point::Point;
+
+//Transmission of a custom aperture, as the cutouts put in front of a lens
+//The image is inscribed in the disk of the lens, its corners on the circle, so none of it is cut
+#[derive(Debug)]
+pub struct ApertureImage {
+    width : usize,
+    height : usize,
+    cdf : Vec<f64>, //Running sum of the transmissions row by row, 1 at the end
+}
+
+impl ApertureImage {
+    //Transmissions in [0, 1] given row by row from the top, the brighter pixels get more rays
+    pub fn new(width : usize, height : usize, transmissions : &[f64]) -> ApertureImage {
+        assert_eq!(transmissions.len(), width*height, "The number of pixels doesn't match the size of the image");
+        let mut sum = 0.;
+        let mut cdf : Vec<f64> = transmissions.iter().map(|transmission| {
+            sum += transmission.max(0.);
+            sum
+        }).collect();
+        assert!(sum > 0., "An aperture lets some light in");
+        for value in &mut cdf {
+            *value /= sum;
+        }
+        ApertureImage { width, height, cdf }
+    }
+
+    //Point of the square inscribed in the unit disk, with y up
+    fn sample(&self, (u, v) : (f64, f64)) -> (f64, f64) {
+        let pixel = self.cdf.partition_point(|&value| value <= u).min(self.cdf.len() - 1);
+        //What is left of u places the point in the pixel
+        let before = if pixel == 0 { 0. } else { self.cdf[pixel - 1] };
+        let x = ((u - before)/(self.cdf[pixel] - before)).clamp(0., 1.);
+        let (i, j) = (pixel%self.width, pixel/self.width);
+        ((2.*(i as f64 + x)/self.width as f64 - 1.)/SQRT_2, (1. - 2.*(j as f64 + v)/self.height as f64)/SQRT_2)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Disk,
+    //Regular polygon of so many blades, with its corners on the circle
+    Polygon(u32),
+    Image(Arc<ApertureImage>),
+}
+
+//Shape of the lens opening, which is the shape of the out of focus highlights
+#[derive(Debug, Clone)]
+pub struct Aperture {
+    pub shape : Shape,
+    pub rotation : f64, //Of the polygon or the image, in degrees
+    pub squeeze : f64, //Anamorphic lenses are narrower across by this factor, their bokeh is oval
+    pub cat_eye : f64, //How much the barrel of the lens cuts the aperture toward the corners of the image, 0 for none
+}
+
+impl Default for Aperture {
+    fn default() -> Aperture {
+        Aperture {
+            shape : Shape::Disk,
+            rotation : 0.,
+            squeeze : 1.,
+            cat_eye : 0.,
+        }
+    }
+}
+
+impl Aperture {
+    pub fn new(shape : Shape) -> Aperture {
+        Aperture { shape, ..Aperture::default() }
+    }
+
+    //Point of the lens in the disk of radius 1 from the numbers of the sampler, None when the barrel blocks it
+    //film is the position on the image, 1 at the corners
+    pub fn sample(&self, (u, v) : (f64, f64), film : (f64, f64)) -> Option<(f64, f64)> {
+        let (x, y) = match &self.shape {
+            Shape::Disk => {
+                let p = Point::in_circle(1., (u, v));
+                (p.x, p.y)
+            },
+            Shape::Polygon(blades) => {
+                //Uniform in one of the triangles between the center and a side
+                let blades = (*blades).max(3);
+                let blade = ((u*blades as f64) as u32).min(blades - 1);
+                let u = u*blades as f64 - blade as f64;
+                let angle = |k : u32| 2.*PI*k as f64/blades as f64 + PI/2.;
+                let (a, b) = (angle(blade), angle(blade + 1));
+                let (s, t) = (u.sqrt()*(1. - v), u.sqrt()*v);
+                (s*a.cos() + t*b.cos(), s*a.sin() + t*b.sin())
+            },
+            Shape::Image(image) => image.sample((u, v)),
+        };
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, y) = ((x*cos - y*sin)/self.squeeze, x*sin + y*cos);
+        //The rays to the sides of the image also pass the back of the barrel, a disk moved outward
+        let (cx, cy) = (film.0*self.cat_eye, film.1*self.cat_eye);
+        //With some slack for the rounding of the points on the circle, as the corners of an image
+        if (x - cx)*(x - cx) + (y - cy)*(y - cy) > 1. + 1e-12 {
+            return None;
+        }
+        Some((x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(aperture : &Aperture, film : (f64, f64)) -> Vec<Option<(f64, f64)>> {
+        (0..64*64).map(|k| aperture.sample((((k%64) as f64 + 0.5)/64., ((k/64) as f64 + 0.5)/64.), film)).collect()
+    }
+
+    #[test]
+    fn shapes() {
+        //A square wouldn't reach the radius of 1 in the diagonal only
+        let disk = points(&Aperture::default(), (0., 0.));
+        assert!(disk.iter().all(|p| p.is_some_and(|(x, y)| x*x + y*y <= 1.)));
+        assert!(disk.iter().flatten().any(|(x, y)| (x*x + y*y).sqrt() > 0.98 && (x.abs() - y.abs()).abs() < 0.05));
+
+        //A hexagon has no point past its sides, and it turns with the rotation
+        let mut hexagon = Aperture::new(Shape::Polygon(6));
+        let apothem = (PI/6.).cos();
+        assert!(points(&hexagon, (0., 0.)).iter().flatten().all(|(x, _)| x.abs() <= apothem + 1e-9));
+        assert!(points(&hexagon, (0., 0.)).iter().flatten().any(|(_, y)| *y > 0.95));
+        hexagon.rotation = 90.;
+        assert!(points(&hexagon, (0., 0.)).iter().flatten().all(|(_, y)| y.abs() <= apothem + 1e-9));
+
+        //Twice as narrow across
+        let anamorphic = Aperture { squeeze : 2., ..Aperture::default() };
+        assert!(points(&anamorphic, (0., 0.)).iter().flatten().all(|(x, _)| x.abs() <= 0.5));
+
+        //Toward the corners, a part of the rays is blocked
+        let cat_eye = Aperture { cat_eye : 0.5, ..Aperture::default() };
+        let blocked = |film| points(&cat_eye, film).iter().filter(|p| p.is_none()).count();
+        assert_eq!(blocked((0., 0.)), 0);
+        assert!(blocked((0.7, 0.7)) > 64*64/4);
+
+        //Only the transmitting pixels of the image, here the right column
+        let image = Arc::new(ApertureImage::new(2, 2, &[0., 1., 0., 1.]));
+        let aperture = Aperture::new(Shape::Image(image));
+        assert!(points(&aperture, (0., 0.)).iter().flatten().all(|(x, _)| *x >= 0.));
+
+        //The corners of the image reach the edge of the lens, none of their rays is lost
+        let corners = Arc::new(ApertureImage::new(3, 3, &[1., 0., 1., 0., 0., 0., 1., 0., 1.]));
+        let mut aperture = Aperture::new(Shape::Image(corners));
+        aperture.rotation = 45.;
+        let corners = points(&aperture, (0., 0.));
+        assert!(corners.iter().all(|p| p.is_some_and(|(x, y)| x*x + y*y > 1./9. && x*x + y*y <= 1. + 1e-12)));
+        assert!(corners.iter().flatten().any(|(x, y)| x*x + y*y > 0.95));
+    }
+}
@@ -0,0 +1,43 @@
+use std::f64::consts::PI;
+
+use crate::{point::Point, ray::Ray};
+use super::Projection;
+
+//360° panorama : the longitude goes along the width, the latitude along the height
+//In stereo the eyes turn with the direction looked at (omni-directional stereo), for the VR headsets
+#[derive(Debug, Clone)]
+pub struct Equirectangular {
+    image_width : f64,
+    image_height : f64,
+    interpupillary : Option<f64>, //Distance between the eyes, the left one is on the top half
+}
+
+impl Equirectangular {
+    pub fn new(image_width : u32, image_height : u32, interpupillary : Option<f64>) -> Equirectangular {
+        Equirectangular {
+            image_width : image_width as f64,
+            image_height : image_height as f64,
+            interpupillary,
+        }
+    }
+}
+
+impl Projection for Equirectangular {
+    fn ray(&self, x : f64, y : f64, _lens : (f64, f64)) -> Option<Ray> {
+        let (eye_height, y, eye) = match self.interpupillary {
+            None => (self.image_height, y, 0.),
+            Some(distance) => {
+                let half = self.image_height/2.;
+                if y + 0.5 < half { (half, y, -distance/2.) } else { (half, y - half, distance/2.) }
+            },
+        };
+        //The center of the image looks forward
+        let longitude = ((x + 0.5)/self.image_width - 0.5)*2.*PI;
+        let latitude = (0.5 - (y + 0.5)/eye_height)*PI;
+
+        let dir = Point { x: longitude.sin()*latitude.cos(), y: latitude.sin(), z: longitude.cos()*latitude.cos() };
+        //The eyes are on the line at right angle with the horizontal direction
+        let right = Point { x: longitude.cos(), y: 0., z: -longitude.sin() };
+        Some(Ray::new(right*eye, dir, 0))
+    }
+}
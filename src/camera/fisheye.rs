@@ -0,0 +1,60 @@
+use std::f64::consts::PI;
+
+use crate::{point::Point, ray::Ray};
+use super::Projection;
+
+//How the angle from the axis of the lens is spread on the film
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mapping {
+    //The distance to the center grows as the angle, the usual measuring fisheye
+    Equidistant,
+    //Each pixel sees the same solid angle, as most photographic fisheyes
+    Equisolid,
+}
+
+//Wide angle camera whose rays spread around the axis, up to all around
+#[derive(Debug, Clone)]
+pub struct Fisheye {
+    image_width : f64,
+    image_height : f64,
+    half_fov : f64, //Angle at the edge of the circle inscribed in the image, in radians
+    mapping : Mapping,
+}
+
+impl Fisheye {
+    pub fn new(image_width : u32, image_height : u32, fov : f64, mapping : Mapping) -> Fisheye {
+        Fisheye {
+            image_width : image_width as f64,
+            image_height : image_height as f64,
+            half_fov : fov.to_radians()/2.,
+            mapping,
+        }
+    }
+}
+
+impl Projection for Fisheye {
+    fn ray(&self, x : f64, y : f64, _lens : (f64, f64)) -> Option<Ray> {
+        //Distance to the center of the image, 1 on the inscribed circle
+        let radius = self.image_width.min(self.image_height)/2.;
+        let (dx, dy) = ((x + 0.5 - self.image_width/2.)/radius, (y + 0.5 - self.image_height/2.)/radius);
+        let r = (dx*dx + dy*dy).sqrt();
+
+        let theta = match self.mapping {
+            Mapping::Equidistant => r*self.half_fov,
+            Mapping::Equisolid => {
+                let sine = r*(self.half_fov/2.).sin();
+                if sine > 1. {
+                    return None;
+                }
+                2.*sine.asin()
+            },
+        };
+        //The corners can go past the back of the lens
+        if theta > PI {
+            return None;
+        }
+
+        let (x, y) = if r > 1e-12 { (dx/r, -dy/r) } else { (0., 0.) };
+        Some(Ray::new(Point { x: 0., y: 0., z: 0. }, Point { x: x*theta.sin(), y: y*theta.sin(), z: theta.cos() }, 0))
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{point::Point, ray::Ray};
+use super::Projection;
+
+//Parallel rays from a rectangle facing the look direction, the sizes don't shrink with the distance
+#[derive(Debug, Clone)]
+pub struct Orthographic {
+    pixel_delta_u : Point<f64>,
+    pixel_delta_v : Point<f64>,
+    pixel00_loc : Point<f64>,
+}
+
+impl Orthographic {
+    pub fn new(image_width : u32, image_height : u32, view_height : f64) -> Orthographic {
+        let viewport_u = Point { x: view_height*image_width as f64/image_height as f64, y: 0., z: 0. };
+        let viewport_v = Point { x: 0., y: -view_height, z: 0. };
+        let pixel_delta_u = viewport_u/(image_width as f64);
+        let pixel_delta_v = viewport_v/(image_height as f64);
+
+        Orthographic {
+            pixel_delta_u,
+            pixel_delta_v,
+            pixel00_loc : (viewport_u + viewport_v)/-2. + pixel_delta_u*0.5 + pixel_delta_v*0.5,
+        }
+    }
+}
+
+impl Projection for Orthographic {
+    fn ray(&self, x : f64, y : f64, _lens : (f64, f64)) -> Option<Ray> {
+        Some(Ray::new(self.pixel00_loc + self.pixel_delta_u*x + self.pixel_delta_v*y, Point { x: 0., y: 0., z: 1. }, 0))
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{point::Point, ray::Ray};
+use super::Projection;
+
+//One surface of a lens prescription, the lengths are in millimeters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    pub radius : f64, //Of curvature, positive when the center is toward the film, 0 for the aperture stop
+    pub thickness : f64, //Distance to the next surface toward the film
+    pub ior : f64, //Of the glass after the surface toward the film, 1 for the air and 0 for the stop
+    pub aperture : f64, //Diameter of the surface
+}
+
+impl LensElement {
+    pub fn new(radius : f64, thickness : f64, ior : f64, aperture : f64) -> LensElement {
+        LensElement { radius, thickness, ior, aperture }
+    }
+}
+
+//The double Gauss 50mm of Kolb, Mitchell and Hanrahan, from the front to the film
+pub fn double_gauss() -> Vec<LensElement> {
+    [
+        (29.475, 3.76, 1.67, 25.2),
+        (84.83, 0.12, 1., 25.2),
+        (19.275, 4.025, 1.67, 23.),
+        (40.77, 3.275, 1.699, 23.),
+        (12.75, 5.705, 1., 18.),
+        (0., 4.5, 0., 17.1),
+        (-14.495, 1.18, 1.603, 17.),
+        (40.77, 6.065, 1.658, 20.),
+        (-20.385, 0.19, 1., 20.),
+        (437.065, 3.22, 1.717, 20.),
+        (-39.73, 5., 1., 20.),
+    ].iter().map(|&(radius, thickness, ior, aperture)| LensElement::new(radius, thickness, ior, aperture)).collect()
+}
+
+//Camera tracing the rays through every glass surface of a lens, from the film to the scene
+//The lens is in the space of the camera with the film at z = 0 and the lens toward -z while tracing,
+//the rays leaving the front are turned back toward +z
+#[derive(Debug, Clone)]
+pub struct Realistic {
+    elements : Vec<LensElement>,
+    film_width : f64, //In millimeters
+    film_height : f64,
+    image_width : f64,
+    image_height : f64,
+}
+
+impl Realistic {
+    //The film is moved so the points at focus_dist (in meters, as the scene) are sharp
+    pub fn new(elements : Vec<LensElement>, image_width : u32, image_height : u32, film_diagonal : f64, focus_dist : f64) -> Realistic {
+        assert!(!elements.is_empty(), "A lens needs at least one element");
+        let (width, height) = (image_width as f64, image_height as f64);
+        let scale = film_diagonal/(width*width + height*height).sqrt();
+        let mut lens = Realistic {
+            elements,
+            film_width : width*scale,
+            film_height : height*scale,
+            image_width : width,
+            image_height : height,
+        };
+        if let Some(back_focus) = lens.focus(focus_dist*1000.) {
+            lens.elements.last_mut().expect("Checked above").thickness = back_focus;
+        }
+        lens
+    }
+
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|element| element.thickness).sum()
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.elements.last().expect("Checked in new()").thickness
+    }
+
+    //From a point of the film toward the lens, None if a surface stops the ray
+    fn trace_from_film(&self, orig : Point<f64>, dir : Point<f64>) -> Option<(Point<f64>, Point<f64>)> {
+        let (mut orig, mut dir) = (flip(orig), flip(dir));
+        let mut z = 0.;
+        for (index, element) in self.elements.iter().enumerate().rev() {
+            z -= element.thickness;
+            let ior_before = match index.checked_sub(1).map(|before| self.elements[before].ior) {
+                Some(ior) if ior != 0. => ior,
+                _ => 1.,
+            };
+            (orig, dir) = interface(element, z, orig, dir, element.ior, ior_before)?;
+        }
+        Some((flip(orig), flip(dir)))
+    }
+
+    //From the scene toward the film
+    fn trace_from_scene(&self, orig : Point<f64>, dir : Point<f64>) -> Option<(Point<f64>, Point<f64>)> {
+        let (mut orig, mut dir) = (flip(orig), flip(dir));
+        let mut z = -self.front_z();
+        for (index, element) in self.elements.iter().enumerate() {
+            let ior_before = match index.checked_sub(1).map(|before| self.elements[before].ior) {
+                Some(ior) if ior != 0. => ior,
+                _ => 1.,
+            };
+            let ior_after = if element.ior != 0. { element.ior } else { 1. };
+            (orig, dir) = interface(element, z, orig, dir, ior_before, ior_after)?;
+            z += element.thickness;
+        }
+        Some((flip(orig), flip(dir)))
+    }
+
+    //Distance from the rear element to the film focusing at the distance from the film, with the thick
+    //lens approximation from the principal planes and the focal points of both sides
+    fn focus(&self, distance : f64) -> Option<f64> {
+        let height = 0.001*(self.film_width*self.film_width + self.film_height*self.film_height).sqrt();
+
+        let scene_orig = Point { x: height, y: 0., z: self.front_z() + 1. };
+        let (orig, dir) = self.trace_from_scene(scene_orig, Point { x: 0., y: 0., z: -1. })?;
+        let (principal_scene, focal_scene) = cardinal_points(scene_orig, orig, dir);
+        let film_orig = Point { x: height, y: 0., z: self.rear_z() - 1. };
+        let (orig, dir) = self.trace_from_film(film_orig, Point { x: 0., y: 0., z: 1. })?;
+        let (principal_film, focal_film) = cardinal_points(film_orig, orig, dir);
+
+        let focal_length = focal_scene - principal_scene;
+        let z = -distance;
+        let c = (principal_film - z - principal_scene)*(principal_film - z - 4.*focal_length - principal_scene);
+        if c < 0. || !focal_film.is_finite() {
+            return None;
+        }
+        let delta = 0.5*(principal_film - z + principal_scene - c.sqrt());
+        Some(self.rear_z() + delta)
+    }
+}
+
+impl Projection for Realistic {
+    fn ray(&self, x : f64, y : f64, lens : (f64, f64)) -> Option<Ray> {
+        //The lens turns the image upside down, the film is read the other way round
+        let film = Point {
+            x: -((x + 0.5)/self.image_width - 0.5)*self.film_width,
+            y: ((y + 0.5)/self.image_height - 0.5)*self.film_height,
+            z: 0.,
+        };
+        //Toward a point of the rear element
+        let rear = self.elements.last().expect("Checked in new()");
+        let target = Point { x: lens.0*rear.aperture/2., y: lens.1*rear.aperture/2., z: self.rear_z() };
+        let (orig, dir) = self.trace_from_film(film, target - film)?;
+        //Millimeters to meters
+        Some(Ray::new(orig*0.001, dir, 0))
+    }
+}
+
+fn flip(p : Point<f64>) -> Point<f64> {
+    Point { x: p.x, y: p.y, z: -p.z }
+}
+
+//Cross one surface at z along the axis, from the glass of index ior_in into ior_out
+fn interface(element : &LensElement, z : f64, orig : Point<f64>, dir : Point<f64>, ior_in : f64, ior_out : f64) -> Option<(Point<f64>, Point<f64>)> {
+    let (t, normal) = if element.radius == 0. {
+        ((z - orig.z)/dir.z, None)
+    } else {
+        let (t, normal) = sphere_hit(element.radius, z + element.radius, orig, dir)?;
+        (t, Some(normal))
+    };
+    if !t.is_finite() || t < 0. {
+        return None;
+    }
+    let hit = orig + dir*t;
+    if hit.x*hit.x + hit.y*hit.y > element.aperture*element.aperture/4. {
+        return None;
+    }
+    match normal {
+        None => Some((hit, dir)),
+        Some(normal) => Some((hit, refract((dir*-1.).unit(), normal, ior_in/ior_out)?)),
+    }
+}
+
+//Hit of the surface of the sphere centered on the axis at center_z, with the normal facing the ray
+fn sphere_hit(radius : f64, center_z : f64, orig : Point<f64>, dir : Point<f64>) -> Option<(f64, Point<f64>)> {
+    let o = Point { x: orig.x, y: orig.y, z: orig.z - center_z };
+    let (a, half_b, c) = (dir.norm_squared(), dir&o, o.norm_squared() - radius*radius);
+    let delta = half_b*half_b - a*c;
+    if delta < 0. {
+        return None;
+    }
+    let sqrt_delta = delta.sqrt();
+    let (t0, t1) = ((-half_b - sqrt_delta)/a, (-half_b + sqrt_delta)/a);
+    //The side of the sphere the lens surface is on
+    let t = if (dir.z > 0.) != (radius < 0.) { t0.min(t1) } else { t0.max(t1) };
+    if t < 0. {
+        return None;
+    }
+    let normal = (o + dir*t).unit();
+    Some((t, if (normal&dir) > 0. { normal*-1. } else { normal }))
+}
+
+//incoming points back along the ray, eta is the ratio of the indices, None for a total internal reflection
+fn refract(incoming : Point<f64>, normal : Point<f64>, eta : f64) -> Option<Point<f64>> {
+    let cos_in = normal&incoming;
+    let sin2_out = eta*eta*(1. - cos_in*cos_in).max(0.);
+    if sin2_out >= 1. {
+        return None;
+    }
+    let cos_out = (1. - sin2_out).sqrt();
+    Some(incoming*-eta + normal*(eta*cos_in - cos_out))
+}
+
+//Where the principal plane and the focal point are along z, from a ray parallel to the axis and
+//what comes out of the lens
+fn cardinal_points(input : Point<f64>, orig : Point<f64>, dir : Point<f64>) -> (f64, f64) {
+    let focal = -(orig.z + dir.z*(-orig.x/dir.x));
+    let principal = -(orig.z + dir.z*((input.x - orig.x)/dir.x));
+    (principal, focal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Where a ray of the xz plane crosses the axis
+    fn axis_crossing(ray : &Ray) -> f64 {
+        ray.orig().z - ray.orig().x*ray.dir().z/ray.dir().x
+    }
+
+    #[test]
+    fn lens_system() {
+        let lens = Realistic::new(double_gauss(), 300, 200, 43.27, 2.);
+
+        //On the axis, the ray goes straight through
+        let ray = lens.ray(149.5, 99.5, (0., 0.)).unwrap();
+        assert!(ray.orig().x.abs() < 1e-9 && ray.orig().y.abs() < 1e-9);
+        assert!(ray.dir().x.abs() < 1e-9 && ray.dir().y.abs() < 1e-9 && ray.dir().z > 0.);
+
+        //The rays from the center of the film close to the axis meet at the focus distance, the ones
+        //through the edge of the lens a bit before it (spherical aberration)
+        let paraxial = axis_crossing(&lens.ray(149.5, 99.5, (0.05, 0.)).unwrap());
+        assert!((paraxial - 2.).abs() < 0.05, "{}", paraxial);
+        assert!(axis_crossing(&lens.ray(149.5, 99.5, (0.6, 0.)).unwrap()) < paraxial);
+        //About 50mm, the film is a bit farther from the lens than for infinity
+        let infinity = Realistic::new(double_gauss(), 300, 200, 43.27, 1e9);
+        assert!(infinity.rear_z() > 30. && infinity.rear_z() < lens.rear_z());
+
+        //The image is turned back up, the right of the image sees on the right
+        let right = lens.ray(225., 99.5, (0., 0.)).unwrap();
+        let top = lens.ray(149.5, 50., (0., 0.)).unwrap();
+        assert!(right.dir().x > 0. && top.dir().y > 0.);
+
+        //The barrel and the stop block more rays toward the corners
+        let blocked = |x, y| (0..32*32).filter(|k| {
+            let lens_point = Point::in_circle(1., (((k%32) as f64 + 0.5)/32., ((k/32) as f64 + 0.5)/32.));
+            lens.ray(x, y, (lens_point.x, lens_point.y)).is_none()
+        }).count();
+        assert!(blocked(0., 0.) > blocked(149.5, 99.5) + 32*32/10);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::point::Point;
+use super::Camera;
+
+//How the two cameras of the rig are aimed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rig {
+    //Side by side and looking the same way, what is at infinity is on the screen
+    Parallel,
+    //Parallel with the image of each eye moved toward the other, what is at the convergence distance is on the screen
+    //without the keystone of turning the cameras in
+    OffAxis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+//Two cameras for a stereoscopic image, with the camera the rig is built on in the middle of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+    pub interaxial : f64, //Distance between the two cameras
+    pub convergence : f64, //Distance of the plane of the screen, for the off-axis rig
+    pub rig : Rig,
+}
+
+impl Stereo {
+    pub fn new(interaxial : f64, convergence : f64, rig : Rig) -> Stereo {
+        Stereo { interaxial, convergence, rig }
+    }
+
+    //The camera of one eye, the others settings are the ones of the center camera
+    pub fn eye(&self, camera : &Camera, eye : Eye) -> Camera {
+        let side = match eye {
+            Eye::Left => -1.,
+            Eye::Right => 1.,
+        };
+        let mut eye_camera = camera.clone();
+        eye_camera.view.origin = camera.view.origin + camera.view.right*(side*self.interaxial/2.);
+        if self.rig == Rig::OffAxis {
+            //The center of the image of each eye sees the point in front of the rig at the convergence distance
+            if let Some(width) = self.pixel_width(camera) {
+                eye_camera.film_shift -= side*self.interaxial/2./width;
+            }
+        }
+        eye_camera
+    }
+
+    //Width in the scene of a pixel in the middle of the image at the convergence distance, from the rays of the
+    //projection so it holds for every model
+    fn pixel_width(&self, camera : &Camera) -> Option<f64> {
+        let projection = camera.model().as_projection();
+        let (x, y) = ((camera.image_width as f64 - 1.)/2., (camera.image_height as f64 - 1.)/2.);
+        let at_convergence = |x : f64| -> Option<Point<f64>> {
+            let ray = projection.ray(x + camera.film_shift, y, (0., 0.))?;
+            Some(*ray.orig() + *ray.dir()*((self.convergence - ray.orig().z)/ray.dir().z))
+        };
+        let width = at_convergence(x + 1.)?.x - at_convergence(x)?.x;
+        (width.is_finite() && width > 0.).then_some(width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{Sampler, SamplerKind};
+
+    //Where the ray through the center of the image crosses the plane at distance 4 in front of the rig
+    fn center_hit(camera : &Camera) -> Point<f64> {
+        let ray = camera.film_ray(49.5, 24.5, &mut Sampler::new(SamplerKind::Independent, 0, 0, 0, 0, 1)).unwrap();
+        *ray.orig() + *ray.dir()*((-4. - ray.orig().z)/ray.dir().z)
+    }
+
+    #[test]
+    fn rigs() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 100, 2., 40., 0., 4.);
+
+        //The eyes are apart along the right of the camera and see the same way
+        let parallel = Stereo::new(0.065, 4., Rig::Parallel);
+        let (left, right) = (parallel.eye(&camera, Eye::Left), parallel.eye(&camera, Eye::Right));
+        assert!(((right.view.origin - left.view.origin).norm() - 0.065).abs() < 1e-12);
+        assert!(((right.view.origin - left.view.origin)&camera.view.right) > 0.);
+        assert!((center_hit(&right) - center_hit(&left) - (right.view.origin - left.view.origin)).norm() < 1e-9);
+
+        //The centers of both images meet at the convergence distance
+        let off_axis = Stereo::new(0.065, 4., Rig::OffAxis);
+        let (left, right) = (off_axis.eye(&camera, Eye::Left), off_axis.eye(&camera, Eye::Right));
+        assert!((center_hit(&left) - Point { x: 0., y: 0., z: -4. }).norm() < 1e-9);
+        assert!((center_hit(&right) - Point { x: 0., y: 0., z: -4. }).norm() < 1e-9);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{point::Point, ray::Ray};
+use super::Projection;
+
+//Movements of a view camera
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Movements {
+    //Off-axis move of the image, in fractions of its width and height toward the right and the top
+    //With a level camera, the vertical lines stay vertical
+    pub shift : (f64, f64),
+    //Turn of the plane of focus around its horizontal line at the focus distance, in degrees
+    //(Scheimpflug), a positive tilt takes the top of the plane away so the ground can be sharp
+    pub tilt : f64,
+    //Same around its vertical line, a positive swing takes the right side away
+    pub swing : f64,
+}
+
+//Perspective through a thin lens, sharp at the focus distance
+#[derive(Debug, Clone)]
+pub struct ThinLens {
+    lens_radius : f64,
+    focus_dist : f64,
+    focus_normal : Point<f64>, //Of the plane of focus
+
+    pixel_delta_u : Point<f64>,
+    pixel_delta_v : Point<f64>,
+    pixel00_loc : Point<f64>,
+}
+
+impl ThinLens {
+    pub fn new(image_width : u32, image_height : u32, vfov : f64, aperture : f64, focus_dist : f64, movements : Movements) -> ThinLens {
+        let theta = vfov.to_radians();
+        let h = (theta/2.).tan()*focus_dist;
+        let viewport_height = 2.0*h;
+        let viewport_width = viewport_height*((image_width as f64)/(image_height as f64));
+
+        let viewport_u = Point { x: viewport_width, y: 0., z: 0. };
+        let viewport_v = Point { x: 0., y: -viewport_height, z: 0. };
+
+        let pixel_delta_u = viewport_u/(image_width as f64);
+        let pixel_delta_v = viewport_v/(image_height as f64);
+
+        //The shift moves the viewport in its plane, it stays parallel to the film
+        let (shift_x, shift_y) = movements.shift;
+        let pixel_up_left = Point { x: 0., y: 0., z: focus_dist } - viewport_u/2.0 - viewport_v/2.0 + viewport_u*shift_x - viewport_v*shift_y;
+        let pixel00_loc = pixel_up_left + pixel_delta_u*0.5 + pixel_delta_v*0.5;
+
+        let focus_normal = Point { x: -movements.swing.to_radians().tan(), y: -movements.tilt.to_radians().tan(), z: 1. }.unit();
+
+        ThinLens {
+            lens_radius : aperture/2.0,
+            focus_dist,
+            focus_normal,
+
+            pixel_delta_u,
+            pixel_delta_v,
+            pixel00_loc,
+        }
+    }
+}
+
+impl Projection for ThinLens {
+    fn ray(&self, x : f64, y : f64, lens : (f64, f64)) -> Option<Ray> {
+        //The ray through the center of the lens meets the plane of focus at the sharp point
+        let chief = self.pixel00_loc + self.pixel_delta_u*x + self.pixel_delta_v*y;
+        let facing = self.focus_normal&chief;
+        let ray_origin = Point { x: lens.0, y: lens.1, z: 0. }*self.lens_radius;
+        if facing <= 1e-12 {
+            //The plane of focus is behind, these points are sharp nowhere but at infinity
+            return Some(Ray::new(ray_origin, chief, 0));
+        }
+        let focus = chief*(self.focus_normal.z*self.focus_dist/facing);
+        Some(Ray::new(ray_origin, focus - ray_origin, 0))
+    }
+}
@@ -1,5 +1,13 @@
-use std::ops::{Mul, Add};
+pub mod display;
 
+use std::{io, ops::{Mul, Add}};
+
+use rand::Rng;
+
+use crate::random::Random;
+use display::Display;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Color<T : Copy> {
     pub r: T,
     pub g: T,
@@ -7,19 +15,19 @@ pub struct Color<T : Copy> {
 }
 
 impl Color<f64> {
-    pub fn write(self, sample_number : u32){
-        let scale : f64 = 1./sample_number as f64;
-        //Applying the Gamma correction
-        let r = (scale*self.r).sqrt();
-        let g = (scale*self.g).sqrt();
-        let b = (scale*self.b).sqrt();
-        
-        //Printing
-        println!("{} {} {}", (r*255.0) as u16, (g*255.0) as u16, (b*255.0) as u16);
+    //Printing the color of the pixel (i, j) of a ppm through the display transform
+    pub fn write<W : io::Write>(self, out : &mut W, display : &Display, i : u32, j : u32) -> io::Result<()> {
+        let [r, g, b] = display.to_srgb8(self, i, j);
+        writeln!(out, "{r} {g} {b}")
+    }
+
+    //Relative luminance (Rec. 709)
+    pub fn luminance(&self) -> f64 {
+        0.2126*self.r + 0.7152*self.g + 0.0722*self.b
     }
 
-    pub fn random() -> Color<f64> {
-        Color { r: rand::random(), g: rand::random(), b: rand::random() }
+    pub fn random(rng : &mut Random) -> Color<f64> {
+        Color { r: rng.gen(), g: rng.gen(), b: rng.gen() }
     }
 }
 
@@ -0,0 +1,219 @@
+use super::Color;
+use crate::random::mix;
+
+//Linear Rec.709 to ACEScg (AP1 primaries, white adapted from D65 to D60 by Bradford)
+const REC709_TO_ACESCG : [[f64; 3]; 3] = [
+    [0.6130974024, 0.3395231462, 0.0473794514],
+    [0.0701937225, 0.9163538791, 0.0134523985],
+    [0.0206155929, 0.1095697729, 0.8698146342],
+];
+const ACESCG_TO_REC709 : [[f64; 3]; 3] = [
+    [1.7050509927, -0.6217921207, -0.0832588720],
+    [-0.1302564176, 1.1408047365, -0.0105483191],
+    [-0.0240033568, -0.1289689760, 1.1529723328],
+];
+
+//Fit of the ACES reference and output transforms by Stephen Hill, from and to linear Rec.709
+const ACES_INPUT : [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+const ACES_OUTPUT : [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+//AgX of Troy Sobotka : inset of the primaries, log encoding between these stops, then a sigmoid
+const AGX_INSET : [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+const AGX_OUTSET : [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+const AGX_MIN_EV : f64 = -12.47393;
+const AGX_MAX_EV : f64 = 4.026069;
+
+//Color space the renderer computes in, the colors of the scene are given in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkingSpace {
+    //Linear with the primaries of sRGB
+    Rec709,
+    //Linear with the wider AP1 primaries of ACES
+    AcesCg,
+}
+
+impl WorkingSpace {
+    //From linear Rec.709, as the images and the colors picked on screen are
+    pub fn from_rec709(&self, color : Color<f64>) -> Color<f64> {
+        match self {
+            WorkingSpace::Rec709 => color,
+            WorkingSpace::AcesCg => transform(&REC709_TO_ACESCG, color),
+        }
+    }
+
+    pub fn to_rec709(&self, color : Color<f64>) -> Color<f64> {
+        match self {
+            WorkingSpace::Rec709 => color,
+            WorkingSpace::AcesCg => transform(&ACESCG_TO_REC709, color),
+        }
+    }
+
+    //Number of the space in the files, it doesn't change with the order of the variants
+    pub fn code(self) -> u32 {
+        match self {
+            WorkingSpace::Rec709 => 0,
+            WorkingSpace::AcesCg => 1,
+        }
+    }
+
+    pub fn from_code(code : u32) -> Option<WorkingSpace> {
+        [WorkingSpace::Rec709, WorkingSpace::AcesCg].into_iter().find(|space| space.code() == code)
+    }
+}
+
+//How the unbounded light is brought into the range of the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapper {
+    //Everything above 1 is white
+    Clamp,
+    //L/(1 + L) on the luminance, so the hues are kept
+    Reinhard,
+    //Filmic curve of ACES, with its contrast and desaturation of the highlights
+    Aces,
+    //Softer filmic curve, the bright saturated colors go to white instead of clipping
+    Agx,
+}
+
+impl ToneMapper {
+    //Linear Rec.709 to linear display values in [0, 1]
+    pub fn map(&self, color : Color<f64>) -> Color<f64> {
+        let color = Color { r: color.r.max(0.), g: color.g.max(0.), b: color.b.max(0.) };
+        let mapped = match self {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => {
+                let luminance = color.luminance();
+                if luminance > 0. { color*(1./(1. + luminance)) } else { color }
+            },
+            ToneMapper::Aces => {
+                let fit = |v : f64| (v*(v + 0.0245786) - 0.000090537)/(v*(0.983729*v + 0.4329510) + 0.238081);
+                let color = transform(&ACES_INPUT, color);
+                transform(&ACES_OUTPUT, Color { r: fit(color.r), g: fit(color.g), b: fit(color.b) })
+            },
+            ToneMapper::Agx => {
+                let curve = |v : f64| {
+                    let x = ((v.max(1e-10).log2() - AGX_MIN_EV)/(AGX_MAX_EV - AGX_MIN_EV)).clamp(0., 1.);
+                    let (x2, x4) = (x*x, x*x*x*x);
+                    15.5*x4*x2 - 40.14*x4*x + 31.96*x4 - 6.868*x2*x + 0.4298*x2 + 0.1191*x - 0.00232
+                };
+                let color = transform(&AGX_INSET, color);
+                let color = transform(&AGX_OUTSET, Color { r: curve(color.r), g: curve(color.g), b: curve(color.b) });
+                //The curve gives values for a 2.2 gamma display
+                Color { r: color.r.max(0.).powf(2.2), g: color.g.max(0.).powf(2.2), b: color.b.max(0.).powf(2.2) }
+            },
+        };
+        Color { r: mapped.r.clamp(0., 1.), g: mapped.g.clamp(0., 1.), b: mapped.b.clamp(0., 1.) }
+    }
+}
+
+//From the light computed to the 8 bits sRGB values written in the images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Display {
+    pub exposure : f64, //In stops, each one doubles the light
+    pub tone_mapper : ToneMapper,
+    pub working_space : WorkingSpace,
+    pub dither : bool, //Noise of one step of 8 bits so the gradients don't band
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display {
+            exposure : 0.,
+            tone_mapper : ToneMapper::Clamp,
+            working_space : WorkingSpace::Rec709,
+            dither : false,
+        }
+    }
+}
+
+impl Display {
+    pub fn new(exposure : f64, tone_mapper : ToneMapper) -> Display {
+        Display { exposure, tone_mapper, ..Display::default() }
+    }
+
+    //For an image rendered in the working space of its scene
+    pub fn in_space(mut self, working_space : WorkingSpace) -> Display {
+        self.working_space = working_space;
+        self
+    }
+
+    //The pixel only changes the dithering
+    pub fn to_srgb8(&self, color : Color<f64>, i : u32, j : u32) -> [u8; 3] {
+        let color = self.tone_mapper.map(self.working_space.to_rec709(color)*2f64.powf(self.exposure));
+        let mut values = [color.r, color.g, color.b].map(linear_to_srgb);
+        if self.dither {
+            for (channel, value) in values.iter_mut().enumerate() {
+                //Triangular noise, the sum of two uniform ones
+                let hash = mix(&[i as u64, j as u64, channel as u64]);
+                let noise = (hash >> 40) as f64/(1u64 << 24) as f64 + (hash & 0xffffff) as f64/(1u64 << 24) as f64 - 1.;
+                *value += noise/255.;
+            }
+        }
+        values.map(|value| (value*255. + 0.5).clamp(0., 255.) as u8)
+    }
+}
+
+//Exact sRGB transfer function, from linear to encoded value
+pub fn linear_to_srgb(value : f64) -> f64 {
+    if value <= 0.0031308 {
+        12.92*value
+    }
+    else {
+        1.055*value.powf(1./2.4) - 0.055
+    }
+}
+
+fn transform(matrix : &[[f64; 3]; 3], color : Color<f64>) -> Color<f64> {
+    let row = |k : usize| matrix[k][0]*color.r + matrix[k][1]*color.g + matrix[k][2]*color.b;
+    Color { r: row(0), g: row(1), b: row(2) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::image::srgb_to_linear;
+
+    #[test]
+    fn display_transform() {
+        //No value goes over 255, however bright
+        let display = Display::default();
+        assert_eq!(display.to_srgb8(Color { r: 40., g: 1., b: -3. }, 0, 0), [255, 255, 0]);
+        assert_eq!(display.to_srgb8(Color { r: 0.216, g: 0.5, b: 0.0031308 }, 0, 0), [128, 188, 10]);
+        assert!((srgb_to_linear(linear_to_srgb(0.3)) - 0.3).abs() < 1e-12);
+        assert_eq!(Display::new(1., ToneMapper::Clamp).to_srgb8(Color { r: 0.108, g: 0.108, b: 0.108 }, 0, 0), [128; 3]);
+
+        //The filmic curves rise steadily up to white
+        for tone_mapper in [ToneMapper::Reinhard, ToneMapper::Aces, ToneMapper::Agx] {
+            let grey = |v : f64| tone_mapper.map(Color { r: v, g: v, b: v }).g;
+            assert!(grey(0.) < 0.01 && grey(0.18) < grey(1.) && grey(1.) < grey(10.) && grey(1000.) > 0.95, "{tone_mapper:?}");
+            assert!((grey(0.18) - 0.18).abs() < 0.1, "{tone_mapper:?}");
+        }
+
+        let color = Color { r: 0.2, g: 0.5, b: 0.9 };
+        let back = WorkingSpace::AcesCg.to_rec709(WorkingSpace::AcesCg.from_rec709(color));
+        assert!((back.r - color.r).abs() + (back.g - color.g).abs() + (back.b - color.b).abs() < 1e-6);
+        //White stays white
+        assert!((WorkingSpace::AcesCg.from_rec709(Color { r: 1., g: 1., b: 1. }).b - 1.).abs() < 1e-3);
+
+        //Dithering moves the values by at most one step
+        let dithered = Display { dither : true, ..Display::default() };
+        let values : Vec<u8> = (0..64).map(|i| dithered.to_srgb8(Color { r: 0.2, g: 0.2, b: 0.2 }, i, 3)[0]).collect();
+        let plain = display.to_srgb8(Color { r: 0.2, g: 0.2, b: 0.2 }, 0, 0)[0];
+        assert!(values.iter().all(|&value| value.abs_diff(plain) <= 1) && values.iter().any(|&value| value != plain));
+    }
+}
@@ -0,0 +1,43 @@
+use std::{fmt, io};
+
+pub mod ply;
+pub mod stl;
+pub mod gltf;
+pub mod pbrt;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    //The file ended before all the announced data was read
+    Truncated(String),
+    //The content of the file is malformed or contradicts itself
+    Invalid(String),
+    //The file is valid but uses a feature we don't handle
+    Unsupported(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "I/O error : {err}"),
+            ImportError::Truncated(msg) => write!(f, "Truncated file : {msg}"),
+            ImportError::Invalid(msg) => write!(f, "Invalid file : {msg}"),
+            ImportError::Unsupported(msg) => write!(f, "Unsupported file : {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(err : io::Error) -> Self {
+        ImportError::Io(err)
+    }
+}
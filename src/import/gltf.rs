@@ -0,0 +1,441 @@
+use std::{fs, path::Path, sync::Arc};
+
+use ::gltf::{Document, Node, json, image::Format, mesh::Mode, camera::Projection, material::AlphaMode};
+
+use crate::{Camera, camera::View, point::Point, color::{Color, display::WorkingSpace}, transform::Transform};
+use crate::world::{World, Surface, mesh::Mesh};
+use crate::material::{Texture, pbr::Pbr, image::ImageTexture};
+use super::ImportError;
+
+//A scene loaded from a glTF file
+pub struct Scene {
+    pub camera : Option<Camera>, //The first camera of the scene, if any
+    pub world : World,
+    pub warnings : Vec<String>, //What was ignored during the import
+}
+
+//Load a glTF 2.0 scene (.gltf with its external or embedded buffers, or .glb)
+//The image width is used to build the camera, its aspect ratio comes from the file
+//The colors of the file, in linear Rec.709, are moved to the working space of the scene
+pub fn load<P : AsRef<Path>>(path : P, image_width : u32, space : WorkingSpace) -> Result<Scene, ImportError> {
+    let path = path.as_ref();
+    parse(&fs::read(path)?, path.parent(), image_width, space)
+}
+
+fn gltf_error(err : ::gltf::Error) -> ImportError {
+    match err {
+        ::gltf::Error::Io(err) => ImportError::Io(err),
+        ::gltf::Error::BufferLength { buffer, expected, actual } =>
+            ImportError::Truncated(format!("Buffer {buffer} should have {expected} bytes but has {actual}")),
+        other => ImportError::Invalid(other.to_string()),
+    }
+}
+
+//Files referenced by the document are looked for in base
+pub fn parse(data : &[u8], base : Option<&Path>, image_width : u32, space : WorkingSpace) -> Result<Scene, ImportError> {
+    let (mut root, blob) : (json::Root, Option<Vec<u8>>) = if data.starts_with(b"glTF") {
+        let glb = ::gltf::binary::Glb::from_slice(data).map_err(gltf_error)?;
+        (json::deserialize::from_slice(&glb.json).map_err(|err| ImportError::Invalid(err.to_string()))?,
+        glb.bin.map(|bin| bin.into_owned()))
+    }
+    else {
+        (json::deserialize::from_slice(data).map_err(|err| ImportError::Invalid(err.to_string()))?, None)
+    };
+
+    //No extension is supported, the required ones are removed so the validation accepts the document
+    let mut warnings = vec![];
+    for extension in &root.extensions_used {
+        warnings.push(format!("Unsupported extension {extension} ignored"));
+    }
+    for extension in root.extensions_required.drain(..) {
+        if !root.extensions_used.contains(&extension) {
+            warnings.push(format!("Unsupported extension {extension} ignored"));
+        }
+    }
+
+    let document = Document::from_json(root).map_err(gltf_error)?;
+    let buffers = ::gltf::import_buffers(&document, base, blob).map_err(gltf_error)?;
+    let images = ::gltf::import_images(&document, base, &buffers).map_err(gltf_error)?;
+
+    let mut importer = Importer {
+        images : &images,
+        textures : vec![None; images.len()],
+        warnings,
+        world : World { working_space : space, ..World::new() },
+        camera : None,
+        image_width,
+    };
+
+    let materials : Vec<Arc<Texture>> = document.materials().map(|material| importer.material(&material)).collect();
+    //Default material of the specification
+    let default_material = Arc::new(Texture::Pbr(Pbr::new(Color { r: 1., g: 1., b: 1. }, None, 1., 1.)));
+
+    //Each primitive of each mesh becomes a surface, shared by all the nodes using the mesh
+    let mut meshes : Vec<Vec<(Arc<Surface>, Arc<Texture>)>> = vec![];
+    for mesh in document.meshes() {
+        let mut primitives = vec![];
+        for primitive in mesh.primitives() {
+            let name = format!("Primitive {} of mesh {}", primitive.index(), mesh.name().unwrap_or(&mesh.index().to_string()));
+            if let Some(surface) = importer.primitive(&primitive, &buffers, &name)? {
+                let material = match primitive.material().index() {
+                    Some(index) => Arc::clone(&materials[index]),
+                    None => Arc::clone(&default_material),
+                };
+                primitives.push((Arc::new(surface), material));
+            }
+        }
+        meshes.push(primitives);
+    }
+
+    if document.animations().next().is_some() {
+        importer.warnings.push(String::from("Animations ignored"));
+    }
+
+    match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                importer.node(&node, Transform::identity(), &meshes, &mut vec![])?;
+            }
+        },
+        None => importer.warnings.push(String::from("The file contains no scene")),
+    }
+
+    Ok(Scene {
+        camera : importer.camera,
+        world : importer.world,
+        warnings : importer.warnings,
+    })
+}
+
+struct Importer<'a> {
+    images : &'a [::gltf::image::Data],
+    textures : Vec<Option<Arc<ImageTexture>>>, //Cache of the converted images
+    warnings : Vec<String>,
+    world : World,
+    camera : Option<Camera>,
+    image_width : u32,
+}
+
+impl Importer<'_> {
+    fn texture(&mut self, index : usize) -> Option<Arc<ImageTexture>> {
+        if let Some(texture) = &self.textures[index] {
+            return Some(Arc::clone(texture));
+        }
+
+        let image = &self.images[index];
+        let (width, height) = (image.width as usize, image.height as usize);
+        //16 bits channels are reduced to their most significant byte
+        let (channels, bytes) = match image.format {
+            Format::R8 => (1, 1),
+            Format::R8G8 => (2, 1),
+            Format::R8G8B8 => (3, 1),
+            Format::R8G8B8A8 => (4, 1),
+            Format::R16 => (1, 2),
+            Format::R16G16 => (2, 2),
+            Format::R16G16B16 => (3, 2),
+            Format::R16G16B16A16 => (4, 2),
+            Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+                self.warnings.push(format!("Floating point image {index} not supported, texture ignored"));
+                return None;
+            },
+        };
+        let data : Vec<u8> = if bytes == 1 {
+            image.pixels.clone()
+        }
+        else {
+            image.pixels.chunks_exact(2).map(|b| (u16::from_ne_bytes([b[0], b[1]]) >> 8) as u8).collect()
+        };
+
+        let texture = Arc::new(ImageTexture::from_srgb8(width, height, &data, channels).in_space(self.world.working_space));
+        self.textures[index] = Some(Arc::clone(&texture));
+        Some(texture)
+    }
+
+    fn material(&mut self, material : &::gltf::Material) -> Arc<Texture> {
+        let name = material.name().map_or_else(|| format!("Material {}", material.index().unwrap_or(0)), String::from);
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+
+        let texture = match pbr.base_color_texture() {
+            Some(info) => {
+                if info.tex_coord() != 0 {
+                    self.warnings.push(format!("{name} : only the first texture coordinates are supported"));
+                }
+                self.texture(info.texture().source().index())
+            },
+            None => None,
+        };
+
+        if pbr.metallic_roughness_texture().is_some() {
+            self.warnings.push(format!("{name} : metallic-roughness texture ignored, the factors are used"));
+        }
+        if material.normal_texture().is_some() {
+            self.warnings.push(format!("{name} : normal map ignored"));
+        }
+        if material.occlusion_texture().is_some() {
+            self.warnings.push(format!("{name} : occlusion texture ignored"));
+        }
+        if material.emissive_factor() != [0., 0., 0.] || material.emissive_texture().is_some() {
+            self.warnings.push(format!("{name} : emission ignored"));
+        }
+        if material.alpha_mode() != AlphaMode::Opaque {
+            self.warnings.push(format!("{name} : transparency ignored, rendered as opaque"));
+        }
+
+        Arc::new(Texture::Pbr(Pbr::new(
+            self.world.working_space.from_rec709(Color { r: r as f64, g: g as f64, b: b as f64 }),
+            texture,
+            pbr.metallic_factor() as f64,
+            pbr.roughness_factor() as f64,
+        )))
+    }
+
+    fn primitive(&mut self, primitive : &::gltf::Primitive, buffers : &[::gltf::buffer::Data], name : &str) -> Result<Option<Surface>, ImportError> {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let vertices : Vec<Point<f64>> = match reader.read_positions() {
+            Some(positions) => positions.map(|[x, y, z]| Point { x: x as f64, y: y as f64, z: z as f64 }).collect(),
+            None => {
+                self.warnings.push(format!("{name} has no position, ignored"));
+                return Ok(None);
+            },
+        };
+        let indices : Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..vertices.len()).collect(),
+        };
+        if let Some(index) = indices.iter().find(|&&i| i >= vertices.len()) {
+            return Err(ImportError::Invalid(format!("{name} uses the vertex {index} but has only {} vertices", vertices.len())));
+        }
+
+        let triangles : Vec<[usize; 3]> = match primitive.mode() {
+            Mode::Triangles => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            //Every other triangle of a strip is reversed to keep the winding
+            Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                .map(|i| if i%2 == 0 { [indices[i], indices[i + 1], indices[i + 2]] } else { [indices[i + 1], indices[i], indices[i + 2]] })
+                .collect(),
+            Mode::TriangleFan => (1..indices.len().saturating_sub(1)).map(|i| [indices[0], indices[i], indices[i + 1]]).collect(),
+            mode => {
+                self.warnings.push(format!("{name} : {mode:?} primitives not supported, ignored"));
+                return Ok(None);
+            },
+        };
+        if triangles.is_empty() {
+            self.warnings.push(format!("{name} has no triangle, ignored"));
+            return Ok(None);
+        }
+        if primitive.morph_targets().next().is_some() {
+            self.warnings.push(format!("{name} : morph targets ignored"));
+        }
+
+        //The accessors of the attributes aren't checked against the positions, the wrong ones are dropped
+        let count = vertices.len();
+        let mut normals : Option<Vec<Point<f64>>> = reader.read_normals().map(|normals| normals.map(|[x, y, z]| Point { x: x as f64, y: y as f64, z: z as f64 }).collect());
+        if normals.as_ref().is_some_and(|normals| normals.len() != count) {
+            self.warnings.push(format!("{name} : wrong number of normals, ignored"));
+            normals = None;
+        }
+        let mut colors : Option<Vec<Color<f64>>> = reader.read_colors(0).map(|colors| colors.into_rgb_f32().map(|[r, g, b]| self.world.working_space.from_rec709(Color { r: r as f64, g: g as f64, b: b as f64 })).collect());
+        if colors.as_ref().is_some_and(|colors| colors.len() != count) {
+            self.warnings.push(format!("{name} : wrong number of colors, ignored"));
+            colors = None;
+        }
+        let mut mesh = Mesh::new(vertices, triangles, normals, colors);
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            let uvs : Vec<(f64, f64)> = uvs.into_f32().map(|[u, v]| (u as f64, v as f64)).collect();
+            if uvs.len() == count {
+                mesh.set_uvs(uvs);
+            }
+            else {
+                self.warnings.push(format!("{name} : wrong number of texture coordinates, ignored"));
+            }
+        }
+        Ok(Some(Surface::Mesh(mesh)))
+    }
+
+    //path holds the indices of the nodes from the root, a node can't be its own ancestor
+    fn node(&mut self, node : &Node, parent : Transform, meshes : &[Vec<(Arc<Surface>, Arc<Texture>)>], path : &mut Vec<usize>) -> Result<(), ImportError> {
+        if path.contains(&node.index()) {
+            return Err(ImportError::Invalid(format!("Node {} is its own ancestor", node.index())));
+        }
+        let name = node.name().map_or_else(|| format!("Node {}", node.index()), String::from);
+        let columns = node.transform().matrix().map(|column| column.map(|v| v as f64));
+        let transform = match Transform::from_columns(columns) {
+            Some(local) => parent*local,
+            None => {
+                self.warnings.push(format!("{name} has a singular transformation, ignored with its children"));
+                return Ok(());
+            },
+        };
+
+        if let Some(mesh) = node.mesh() {
+            for (surface, material) in &meshes[mesh.index()] {
+                self.world.add_instance(Arc::clone(surface), transform, Arc::clone(material));
+            }
+        }
+        if node.skin().is_some() {
+            self.warnings.push(format!("{name} : skinning ignored"));
+        }
+        if let Some(camera) = node.camera() {
+            self.camera(&camera, &transform, &name);
+        }
+
+        path.push(node.index());
+        for child in node.children() {
+            self.node(&child, transform, meshes, path)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    fn camera(&mut self, camera : &::gltf::Camera, transform : &Transform, name : &str) {
+        if self.camera.is_some() {
+            self.warnings.push(format!("{name} : only the first camera is used"));
+            return;
+        }
+        //A glTF camera looks toward -z with y up
+        let origin = transform.point(Point { x: 0., y: 0., z: 0. });
+        let forward = transform.vector(Point { x: 0., y: 0., z: -1. });
+        let up = transform.vector(Point { x: 0., y: 1., z: 0. });
+
+        let mut camera = match camera.projection() {
+            Projection::Perspective(perspective) => {
+                let aspect_ratio = perspective.aspect_ratio().map_or(16./9., |ratio| ratio as f64);
+                Camera::new(origin, origin + forward, self.image_width, aspect_ratio, (perspective.yfov() as f64).to_degrees(), 0., 1.)
+            },
+            //The magnifications are the half sizes of the view
+            Projection::Orthographic(orthographic) => {
+                let (xmag, ymag) = (orthographic.xmag() as f64, orthographic.ymag() as f64);
+                Camera::orthographic(origin, origin + forward, self.image_width, xmag/ymag, 2.*ymag)
+            },
+        };
+        //The up of the node keeps the roll of the camera, the view is right handed as glTF
+        camera.view = View::look_at(origin, origin + forward, up, 0.).right_handed();
+        self.camera = Some(camera);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ray::Ray, sampler::{Sampler, SamplerKind}};
+
+    //Build a .glb with the json and the binary chunk
+    fn glb(json : &str, bin : &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let mut data = b"glTF".to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&(12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"JSON");
+        data.extend_from_slice(&json);
+        data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"BIN\0");
+        data.extend_from_slice(&bin);
+        data
+    }
+
+    #[test]
+    fn triangle_hierarchy_and_camera() {
+        //One triangle in the plane z = 0, placed at z = -5 by a parent node
+        let mut bin = vec![];
+        for v in [-1f32, -1., 0., 1., -1., 0., 0., 1., 0.] {
+            bin.extend_from_slice(&v.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "scene": 0,
+            "scenes": [{"nodes": [0, 2]}],
+            "nodes": [
+                {"translation": [0, 0, -5], "children": [1]},
+                {"mesh": 0},
+                {"camera": 0}
+            ],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "aspectRatio": 2.0, "znear": 0.1}}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+            "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}],
+            "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [-1, -1, 0], "max": [1, 1, 0]}],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}],
+            "buffers": [{"byteLength": 36}]
+        }"#;
+
+        let scene = parse(&glb(json, &bin), None, 200, WorkingSpace::Rec709).unwrap();
+        assert_eq!(scene.warnings, vec![String::from("Unsupported extension KHR_lights_punctual ignored")]);
+
+        let camera = scene.camera.expect("The camera should be loaded");
+        assert_eq!((camera.image_width, camera.image_height), (200, 100));
+
+        let ray = Ray::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 0);
+        let (hit, _, _) = ray.object_hitted(&scene.world).expect("The triangle should be in front of the camera");
+        assert!((hit.t - 5.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn keeps_left_and_right() {
+        //A triangle only on the +x side, in front of a camera looking toward -z
+        let mut bin = vec![];
+        for v in [1f32, -1., -5., 3., -1., -5., 2., 1., -5.] {
+            bin.extend_from_slice(&v.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0, 1]}],
+            "nodes": [{"mesh": 0}, {"camera": 0}],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "aspectRatio": 2.0, "znear": 0.1}}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]}],
+            "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [1, -1, -5], "max": [3, 1, -5]}],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}],
+            "buffers": [{"byteLength": 36}]
+        }"#;
+        let scene = parse(&glb(json, &bin), None, 200, WorkingSpace::Rec709).unwrap();
+
+        //The geometry isn't mirrored
+        let toward = |x| Ray::new(Point { x: 0., y: 0., z: 0. }, Point { x, y: 0., z: -5. }, 0);
+        assert!(toward(2.).object_hitted(&scene.world).is_some());
+        assert!(toward(-2.).object_hitted(&scene.world).is_none());
+
+        //And the camera sees it on the right of the image
+        let camera = scene.camera.expect("The camera should be loaded");
+        let mut sampler = Sampler::new(SamplerKind::Independent, 0, 0, 0, 0, 1);
+        let sees = |x, sampler : &mut Sampler| camera.film_ray(x, 49.5, sampler).unwrap().object_hitted(&scene.world).is_some();
+        assert!(sees(150., &mut sampler));
+        assert!(!sees(50., &mut sampler));
+    }
+
+    #[test]
+    fn wrong_attribute_counts() {
+        //3 positions but only 2 normals
+        let mut bin = vec![];
+        for v in [-1f32, -1., -5., 1., -1., -5., 0., 1., -5., 0., 0., 1., 0., 0., 1.] {
+            bin.extend_from_slice(&v.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0, "NORMAL": 1}}]}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [-1, -1, -5], "max": [1, 1, -5]},
+                {"bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3"}
+            ],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}, {"buffer": 0, "byteOffset": 36, "byteLength": 24}],
+            "buffers": [{"byteLength": 60}]
+        }"#;
+        let scene = parse(&glb(json, &bin), None, 100, WorkingSpace::Rec709).unwrap();
+        assert_eq!(scene.warnings, vec![String::from("Primitive 0 of mesh 0 : wrong number of normals, ignored")]);
+        assert_eq!(scene.world.objects.len(), 1);
+    }
+
+    #[test]
+    fn missing_buffer_data() {
+        let json = r#"{"asset": {"version": "2.0"}, "buffers": [{"byteLength": 64}]}"#;
+        assert!(matches!(parse(&glb(json, &[0; 8]), None, 100, WorkingSpace::Rec709), Err(ImportError::Truncated(_))));
+
+        //A node among its own children
+        let json = r#"{"asset": {"version": "2.0"}, "scenes": [{"nodes": [0]}], "nodes": [{"children": [1]}, {"children": [0]}]}"#;
+        assert!(matches!(parse(json.as_bytes(), None, 100, WorkingSpace::Rec709), Err(ImportError::Invalid(_))));
+    }
+}
@@ -0,0 +1,773 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc};
+
+use crate::{Camera, camera::realistic::LensElement, point::Point, color::{Color, display::WorkingSpace}, transform::Transform, render::CropWindow};
+use crate::world::{World, Surface, Background, sphere::Sphere, mesh::Mesh};
+use crate::material::{Texture, diffuse::Diffuse, metal::Metal, dielectric::Dielectric, emissive::Emissive};
+use super::{ImportError, ply};
+
+//Default index of refraction and absorption of the pbrt metal material (copper)
+const COPPER_ETA : Color<f64> = Color { r: 0.200438, g: 0.924033, b: 1.10221 };
+const COPPER_K : Color<f64> = Color { r: 3.91295, g: 2.45285, b: 2.14219 };
+
+//A scene loaded from a pbrt-v3 file
+pub struct Scene {
+    pub camera : Camera,
+    pub world : World,
+    pub samples_per_pixel : u32,
+    pub crop_window : Option<CropWindow>, //The part of the image to render, all of it if None
+    pub warnings : Vec<String>, //What was ignored during the import
+}
+
+//Load a pbrt-v3 scene, the included and ply files are looked for next to it
+//Its rgb colors, in linear Rec.709, are moved to the working space of the scene
+pub fn load<P : AsRef<Path>>(path : P, space : WorkingSpace) -> Result<Scene, ImportError> {
+    let path = path.as_ref();
+    parse(&fs::read_to_string(path)?, path.parent(), space)
+}
+
+//The scene is built in the camera space of pbrt (camera at the origin looking toward +z with y up)
+//so the camera doesn't need any roll and the handedness of the file is kept
+pub fn parse(text : &str, base : Option<&Path>, space : WorkingSpace) -> Result<Scene, ImportError> {
+    let mut tokens = tokenize(text)?;
+    tokens.reverse();
+    let default_material = Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 })));
+
+    let mut parser = Parser {
+        tokens,
+        base : base.map(Path::to_path_buf),
+        includes : vec![],
+        warnings : vec![],
+        attributes : Attributes { transform: Transform::identity(), material: default_material, area_light: None },
+        attribute_stack : vec![],
+        transform_stack : vec![],
+        named_materials : HashMap::new(),
+        coordinate_systems : HashMap::new(),
+        objects : HashMap::new(),
+        current_object : None,
+        world_to_camera : Transform::identity(),
+        camera : None,
+        resolution : (640, 480),
+        film_diagonal : 35.,
+        crop_window : None,
+        samples_per_pixel : 16,
+        infinite_light : Color { r: 0., g: 0., b: 0. },
+        world : World { working_space : space, ..World::new() },
+    };
+    parser.run()?;
+    parser.finish()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String), //A directive, or a bare boolean
+    Str(String),
+    Num(f64),
+    Open,
+    Close,
+    EndInclude, //After the tokens of an included file
+}
+
+fn tokenize(text : &str) -> Result<Vec<Token>, ImportError> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => string.push(c),
+                        None => return Err(ImportError::Truncated(String::from("Unterminated string"))),
+                    }
+                }
+                tokens.push(Token::Str(string));
+            },
+            c if c.is_whitespace() => (),
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|&(_, c)| !c.is_whitespace() && !matches!(c, '"' | '[' | ']' | '#')) {
+                    end = i + c.len_utf8();
+                }
+                let word = &text[start..end];
+                tokens.push(match word.parse::<f64>() {
+                    Ok(number) => Token::Num(number),
+                    Err(_) => Token::Word(String::from(word)),
+                });
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+enum Value {
+    Num(f64),
+    Str(String),
+}
+
+//A parameter as "type name" [ values ]
+struct Param {
+    ty : String,
+    name : String,
+    values : Vec<Value>,
+}
+
+struct Params {
+    list : Vec<Param>,
+}
+
+impl Params {
+    fn get(&self, name : &str) -> Option<&Param> {
+        self.list.iter().find(|param| param.name == name)
+    }
+
+    fn numbers(&self, name : &str) -> Result<Option<Vec<f64>>, ImportError> {
+        let Some(param) = self.get(name) else {
+            return Ok(None);
+        };
+        param.values.iter().map(|value| match value {
+            Value::Num(number) => Ok(*number),
+            Value::Str(string) => Err(ImportError::Invalid(format!("Parameter '{name}' expects numbers, found '{string}'"))),
+        }).collect::<Result<Vec<f64>, ImportError>>().map(Some)
+    }
+
+    fn float(&self, name : &str, default : f64) -> Result<f64, ImportError> {
+        match self.numbers(name)? {
+            Some(values) => values.first().copied().ok_or_else(|| ImportError::Invalid(format!("Parameter '{name}' has no value"))),
+            None => Ok(default),
+        }
+    }
+
+    fn string(&self, name : &str) -> Option<&str> {
+        match self.get(name)?.values.first() {
+            Some(Value::Str(string)) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn bool(&self, name : &str, default : bool) -> bool {
+        self.string(name).map_or(default, |value| value == "true")
+    }
+
+    //Triplets of numbers (points and normals)
+    fn points(&self, name : &str) -> Result<Option<Vec<Point<f64>>>, ImportError> {
+        match self.numbers(name)? {
+            Some(values) if !values.len().is_multiple_of(3) => Err(ImportError::Invalid(format!("Parameter '{name}' needs a multiple of 3 values"))),
+            Some(values) => Ok(Some(values.chunks_exact(3).map(|p| Point { x: p[0], y: p[1], z: p[2] }).collect())),
+            None => Ok(None),
+        }
+    }
+}
+
+//The graphics state saved by AttributeBegin
+#[derive(Clone)]
+struct Attributes {
+    transform : Transform, //Object to world
+    material : Arc<Texture>,
+    area_light : Option<Arc<Texture>>, //Replaces the material of the shapes when set
+}
+
+//Shapes of an object instance, with their transformation relative to the instance
+type Object = Vec<(Arc<Surface>, Transform, Arc<Texture>)>;
+
+struct Parser {
+    tokens : Vec<Token>, //Reversed, the next token is the last one
+    base : Option<PathBuf>,
+    includes : Vec<PathBuf>, //The files being included, each one in the previous one
+    warnings : Vec<String>,
+
+    attributes : Attributes,
+    attribute_stack : Vec<Attributes>,
+    transform_stack : Vec<Transform>,
+    named_materials : HashMap<String, Arc<Texture>>,
+    coordinate_systems : HashMap<String, Transform>,
+    objects : HashMap<String, Object>,
+    current_object : Option<(String, Object)>, //The object being defined
+
+    world_to_camera : Transform,
+    camera : Option<(String, Params)>,
+    resolution : (u32, u32),
+    film_diagonal : f64, //In millimeters, for the realistic camera
+    crop_window : Option<CropWindow>,
+    samples_per_pixel : u32,
+    infinite_light : Color<f64>,
+    world : World,
+}
+
+impl Parser {
+    fn string(&mut self, directive : &str) -> Result<String, ImportError> {
+        match self.tokens.pop() {
+            Some(Token::Str(string)) => Ok(string),
+            Some(token) => Err(ImportError::Invalid(format!("{directive} expects a string, found {token:?}"))),
+            None => Err(ImportError::Truncated(format!("Missing the arguments of {directive}"))),
+        }
+    }
+
+    //The numbers may be enclosed in brackets
+    fn numbers(&mut self, count : usize, directive : &str) -> Result<Vec<f64>, ImportError> {
+        let bracketed = self.tokens.last() == Some(&Token::Open);
+        if bracketed {
+            self.tokens.pop();
+        }
+        let mut numbers = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.tokens.pop() {
+                Some(Token::Num(number)) => numbers.push(number),
+                Some(token) => return Err(ImportError::Invalid(format!("{directive} expects {count} numbers, found {token:?}"))),
+                None => return Err(ImportError::Truncated(format!("Missing the arguments of {directive}"))),
+            }
+        }
+        if bracketed && self.tokens.pop() != Some(Token::Close) {
+            return Err(ImportError::Invalid(format!("{directive} expects {count} numbers in brackets")));
+        }
+        Ok(numbers)
+    }
+
+    fn params(&mut self) -> Result<Params, ImportError> {
+        let mut list = vec![];
+        while let Some(Token::Str(declaration)) = self.tokens.last() {
+            let (ty, name) = match declaration.split_whitespace().collect::<Vec<&str>>()[..] {
+                [ty, name] => (String::from(ty), String::from(name)),
+                _ => return Err(ImportError::Invalid(format!("'{declaration}' is not a parameter declaration"))),
+            };
+            self.tokens.pop();
+
+            let mut values = vec![];
+            let bracketed = self.tokens.last() == Some(&Token::Open);
+            if bracketed {
+                self.tokens.pop();
+            }
+            loop {
+                let value = match self.tokens.pop() {
+                    Some(Token::Num(number)) => Value::Num(number),
+                    Some(Token::Str(string)) | Some(Token::Word(string)) => Value::Str(string),
+                    Some(Token::Close) if bracketed => break,
+                    Some(token) => return Err(ImportError::Invalid(format!("Unexpected {token:?} in the values of '{name}'"))),
+                    None => return Err(ImportError::Truncated(format!("Missing the values of '{name}'"))),
+                };
+                values.push(value);
+                if !bracketed {
+                    break;
+                }
+            }
+            list.push(Param { ty, name, values });
+        }
+        Ok(Params { list })
+    }
+
+    //Skip the arguments of an unsupported directive
+    fn skip(&mut self, directive : &str) {
+        while let Some(token) = self.tokens.last() {
+            if matches!(token, Token::Word(word) if word != "true" && word != "false") || *token == Token::EndInclude {
+                break;
+            }
+            self.tokens.pop();
+        }
+        self.warnings.push(format!("{directive} not supported, ignored"));
+    }
+
+    //A color parameter, in the working space of the scene
+    fn color(&mut self, params : &Params, name : &str, default : Color<f64>) -> Result<Color<f64>, ImportError> {
+        let color = self.rgb(params, name, default)?;
+        Ok(self.world.working_space.from_rec709(color))
+    }
+
+    //A parameter given as rgb, or as a single float for a grey, as it is in the file
+    fn rgb(&mut self, params : &Params, name : &str, default : Color<f64>) -> Result<Color<f64>, ImportError> {
+        let Some(param) = params.get(name) else {
+            return Ok(default);
+        };
+        match param.ty.as_str() {
+            "rgb" | "color" => match params.numbers(name)?.as_deref() {
+                Some([r, g, b]) => Ok(Color { r: *r, g: *g, b: *b }),
+                _ => Err(ImportError::Invalid(format!("Parameter '{name}' needs 3 values"))),
+            },
+            "float" => {
+                let value = params.float(name, 1.)?;
+                Ok(Color { r: value, g: value, b: value })
+            },
+            ty => {
+                self.warnings.push(format!("Parameter '{name}' of type {ty} not supported, default value used"));
+                Ok(default)
+            },
+        }
+    }
+
+    fn transform(&mut self, transform : Transform) {
+        self.attributes.transform = self.attributes.transform*transform;
+    }
+
+    fn run(&mut self) -> Result<(), ImportError> {
+        while let Some(token) = self.tokens.pop() {
+            let directive = match token {
+                Token::Word(word) => word,
+                Token::EndInclude => {
+                    self.includes.pop();
+                    continue;
+                },
+                token => return Err(ImportError::Invalid(format!("Expected a directive, found {token:?}"))),
+            };
+            match directive.as_str() {
+                "Identity" => self.attributes.transform = Transform::identity(),
+                "Translate" => {
+                    let v = self.numbers(3, &directive)?;
+                    self.transform(Transform::translate(Point { x: v[0], y: v[1], z: v[2] }));
+                },
+                "Scale" => {
+                    let v = self.numbers(3, &directive)?;
+                    if v.contains(&0.) {
+                        return Err(ImportError::Invalid(String::from("Scale by 0")));
+                    }
+                    self.transform(Transform::scale(Point { x: v[0], y: v[1], z: v[2] }));
+                },
+                "Rotate" => {
+                    let v = self.numbers(4, &directive)?;
+                    let axis = Point { x: v[1], y: v[2], z: v[3] };
+                    if axis.norm() == 0. {
+                        return Err(ImportError::Invalid(String::from("Rotation around a null axis")));
+                    }
+                    self.transform(Transform::rotate(v[0], axis));
+                },
+                "LookAt" => {
+                    let v = self.numbers(9, &directive)?;
+                    match look_at(Point { x: v[0], y: v[1], z: v[2] }, Point { x: v[3], y: v[4], z: v[5] }, Point { x: v[6], y: v[7], z: v[8] }) {
+                        Some(transform) => self.transform(transform),
+                        None => self.warnings.push(String::from("Degenerate LookAt ignored")),
+                    }
+                },
+                "ConcatTransform" | "Transform" => {
+                    let v = self.numbers(16, &directive)?;
+                    let columns = [0, 4, 8, 12].map(|i| [v[i], v[i + 1], v[i + 2], v[i + 3]]);
+                    let transform = Transform::from_columns(columns).ok_or_else(|| ImportError::Invalid(format!("Singular matrix in {directive}")))?;
+                    if directive == "Transform" {
+                        self.attributes.transform = transform;
+                    }
+                    else {
+                        self.transform(transform);
+                    }
+                },
+                "CoordinateSystem" => {
+                    let name = self.string(&directive)?;
+                    self.coordinate_systems.insert(name, self.attributes.transform);
+                },
+                "CoordSysTransform" => {
+                    let name = self.string(&directive)?;
+                    match self.coordinate_systems.get(&name) {
+                        Some(transform) => self.attributes.transform = *transform,
+                        None => self.warnings.push(format!("Unknown coordinate system '{name}'")),
+                    }
+                },
+                "AttributeBegin" => self.attribute_stack.push(self.attributes.clone()),
+                "AttributeEnd" => match self.attribute_stack.pop() {
+                    Some(attributes) => self.attributes = attributes,
+                    None => self.warnings.push(String::from("Unmatched AttributeEnd ignored")),
+                },
+                "TransformBegin" => self.transform_stack.push(self.attributes.transform),
+                "TransformEnd" => match self.transform_stack.pop() {
+                    Some(transform) => self.attributes.transform = transform,
+                    None => self.warnings.push(String::from("Unmatched TransformEnd ignored")),
+                },
+                "Camera" => {
+                    let ty = self.string(&directive)?;
+                    let params = self.params()?;
+                    self.world_to_camera = self.attributes.transform;
+                    self.coordinate_systems.insert(String::from("camera"), self.attributes.transform.inverse());
+                    self.camera = Some((ty, params));
+                },
+                "Film" => {
+                    self.string(&directive)?;
+                    let params = self.params()?;
+                    self.resolution = (params.float("xresolution", 640.)? as u32, params.float("yresolution", 480.)? as u32);
+                    if self.resolution.0 == 0 || self.resolution.1 == 0 {
+                        return Err(ImportError::Invalid(String::from("Null film resolution")));
+                    }
+                    self.film_diagonal = params.float("diagonal", 35.)?;
+                    //Fractions of the image from x0 to x1 and y0 to y1, from the top left
+                    self.crop_window = match params.numbers("cropwindow")?.as_deref() {
+                        Some(&[x0, x1, y0, y1]) => {
+                            let (width, height) = (self.resolution.0 as f64, self.resolution.1 as f64);
+                            let pixel = |fraction : f64, size : f64| (fraction.clamp(0., 1.)*size).ceil() as u32;
+                            let (x, y) = (pixel(x0, width), pixel(y0, height));
+                            Some(CropWindow::new(x, y, pixel(x1, width).saturating_sub(x), pixel(y1, height).saturating_sub(y)))
+                        },
+                        Some(_) => return Err(ImportError::Invalid(String::from("The crop window needs 4 values"))),
+                        None => None,
+                    };
+                },
+                "Sampler" => {
+                    let ty = self.string(&directive)?;
+                    let params = self.params()?;
+                    self.samples_per_pixel = if ty == "stratified" {
+                        (params.float("xsamples", 2.)?*params.float("ysamples", 2.)?) as u32
+                    }
+                    else {
+                        params.float("pixelsamples", 16.)? as u32
+                    }.max(1);
+                },
+                "WorldBegin" => {
+                    self.attributes.transform = Transform::identity();
+                    self.coordinate_systems.insert(String::from("world"), Transform::identity());
+                },
+                "WorldEnd" => (),
+                "Material" => {
+                    let ty = self.string(&directive)?;
+                    let params = self.params()?;
+                    self.attributes.material = self.material(&ty, &params)?;
+                },
+                "MakeNamedMaterial" => {
+                    let name = self.string(&directive)?;
+                    let params = self.params()?;
+                    let ty = String::from(params.string("type").unwrap_or(""));
+                    let material = self.material(&ty, &params)?;
+                    self.named_materials.insert(name, material);
+                },
+                "NamedMaterial" => {
+                    let name = self.string(&directive)?;
+                    match self.named_materials.get(&name) {
+                        Some(material) => self.attributes.material = Arc::clone(material),
+                        None => self.warnings.push(format!("Unknown material '{name}'")),
+                    }
+                },
+                "AreaLightSource" => {
+                    let ty = self.string(&directive)?;
+                    let params = self.params()?;
+                    if ty == "diffuse" {
+                        let white = Color { r: 1., g: 1., b: 1. };
+                        let radiance = self.color(&params, "L", white)?*self.rgb(&params, "scale", white)?;
+                        self.attributes.area_light = Some(Arc::new(Texture::Emissive(Emissive::new(radiance, params.bool("twosided", false)))));
+                    }
+                    else {
+                        self.warnings.push(format!("Area light '{ty}' not supported, ignored"));
+                    }
+                },
+                "LightSource" => {
+                    let ty = self.string(&directive)?;
+                    let params = self.params()?;
+                    if ty == "infinite" {
+                        let white = Color { r: 1., g: 1., b: 1. };
+                        let radiance = self.color(&params, "L", white)?*self.rgb(&params, "scale", white)?;
+                        if params.get("mapname").is_some() {
+                            self.warnings.push(String::from("Environment maps not supported, the infinite light is uniform"));
+                        }
+                        self.infinite_light = self.infinite_light + radiance;
+                    }
+                    else {
+                        self.warnings.push(format!("Light '{ty}' not supported, only area and infinite lights are, ignored"));
+                    }
+                },
+                "Shape" => {
+                    let ty = self.string(&directive)?;
+                    let params = self.params()?;
+                    if let Some(surface) = self.shape(&ty, &params)? {
+                        let texture = Arc::clone(self.attributes.area_light.as_ref().unwrap_or(&self.attributes.material));
+                        match &mut self.current_object {
+                            Some((_, shapes)) => shapes.push((Arc::new(surface), self.attributes.transform, texture)),
+                            None => self.world.add_instance(Arc::new(surface), self.world_to_camera*self.attributes.transform, texture),
+                        }
+                    }
+                },
+                "ObjectBegin" => {
+                    let name = self.string(&directive)?;
+                    self.attribute_stack.push(self.attributes.clone());
+                    self.current_object = Some((name, vec![]));
+                },
+                "ObjectEnd" => {
+                    if let Some((name, shapes)) = self.current_object.take() {
+                        self.objects.insert(name, shapes);
+                    }
+                    if let Some(attributes) = self.attribute_stack.pop() {
+                        self.attributes = attributes;
+                    }
+                },
+                "ObjectInstance" => {
+                    let name = self.string(&directive)?;
+                    let Some(shapes) = self.objects.get(&name) else {
+                        self.warnings.push(format!("Unknown object '{name}'"));
+                        continue;
+                    };
+                    let instance = self.world_to_camera*self.attributes.transform;
+                    for (surface, transform, texture) in shapes {
+                        self.world.add_instance(Arc::clone(surface), instance*(*transform), Arc::clone(texture));
+                    }
+                },
+                "Include" => {
+                    let file = self.string(&directive)?;
+                    let path = self.path(&file);
+                    let path = fs::canonicalize(&path).unwrap_or(path);
+                    if self.includes.contains(&path) {
+                        return Err(ImportError::Invalid(format!("{} includes itself", path.display())));
+                    }
+                    let mut included = tokenize(&fs::read_to_string(&path)?)?;
+                    included.reverse();
+                    self.tokens.push(Token::EndInclude);
+                    self.tokens.append(&mut included);
+                    self.includes.push(path);
+                },
+                _ => self.skip(&directive),
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, file : &str) -> PathBuf {
+        match &self.base {
+            Some(base) => base.join(file),
+            None => PathBuf::from(file),
+        }
+    }
+
+    fn material(&mut self, ty : &str, params : &Params) -> Result<Arc<Texture>, ImportError> {
+        let texture = match ty {
+            "matte" => Texture::Diffuse(Diffuse::new(self.color(params, "Kd", Color { r: 0.5, g: 0.5, b: 0.5 })?)),
+            "metal" => {
+                //Reflectance at normal incidence of the conductor
+                let eta = self.rgb(params, "eta", COPPER_ETA)?;
+                let k = self.rgb(params, "k", COPPER_K)?;
+                let f0 = |n : f64, k : f64| ((n - 1.)*(n - 1.) + k*k)/((n + 1.)*(n + 1.) + k*k);
+                //The microfacet roughness is used directly as the fuzziness
+                let roughness = match (params.get("uroughness"), params.get("vroughness")) {
+                    (None, None) => params.float("roughness", 0.01)?,
+                    _ => (params.float("uroughness", 0.)? + params.float("vroughness", 0.)?)/2.,
+                };
+                let f0 = self.world.working_space.from_rec709(Color { r: f0(eta.r, k.r), g: f0(eta.g, k.g), b: f0(eta.b, k.b) });
+                Texture::Metal(Metal::new(f0, roughness.clamp(0., 1.)))
+            },
+            "glass" => Texture::Dielectric(Dielectric::new(params.float("index", 1.5)?)),
+            _ => {
+                self.warnings.push(format!("Material '{ty}' not supported, replaced by a matte one"));
+                Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 }))
+            },
+        };
+        Ok(Arc::new(texture))
+    }
+
+    //The shape in its object space, None if it isn't supported
+    fn shape(&mut self, ty : &str, params : &Params) -> Result<Option<Surface>, ImportError> {
+        match ty {
+            "sphere" => {
+                if ["zmin", "zmax", "phimax"].iter().any(|name| params.get(name).is_some()) {
+                    self.warnings.push(String::from("Partial spheres not supported, the whole sphere is used"));
+                }
+                Ok(Some(Surface::Sphere(Sphere::new(Point { x: 0., y: 0., z: 0. }, params.float("radius", 1.)?))))
+            },
+            "trianglemesh" => {
+                let vertices = params.points("P")?.ok_or_else(|| ImportError::Invalid(String::from("Triangle mesh without 'P'")))?;
+                let indices : Vec<usize> = match params.numbers("indices")? {
+                    Some(indices) => indices.iter().map(|&i| {
+                        if i < 0. || i.fract() != 0. || i as usize >= vertices.len() {
+                            Err(ImportError::Invalid(format!("Invalid vertex index {i} in a triangle mesh of {} vertices", vertices.len())))
+                        }
+                        else {
+                            Ok(i as usize)
+                        }
+                    }).collect::<Result<_, _>>()?,
+                    None if vertices.len() == 3 => vec![0, 1, 2],
+                    None => return Err(ImportError::Invalid(String::from("Triangle mesh without 'indices'"))),
+                };
+                if indices.is_empty() || !indices.len().is_multiple_of(3) {
+                    return Err(ImportError::Invalid(String::from("The number of indices of a triangle mesh must be a non zero multiple of 3")));
+                }
+                let triangles = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+                let mut normals = params.points("N")?;
+                if normals.as_ref().is_some_and(|normals| normals.len() != vertices.len()) {
+                    self.warnings.push(String::from("Wrong number of normals in a triangle mesh, ignored"));
+                    normals = None;
+                }
+                let uvs = match params.numbers("uv")? {
+                    Some(uvs) => Some(uvs),
+                    None => params.numbers("st")?,
+                };
+                let count = vertices.len();
+                let mut mesh = Mesh::new(vertices, triangles, normals, None);
+                match uvs {
+                    Some(uvs) if uvs.len() == 2*count => mesh.set_uvs(uvs.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect()),
+                    Some(_) => self.warnings.push(String::from("Wrong number of texture coordinates in a triangle mesh, ignored")),
+                    None => (),
+                }
+                Ok(Some(Surface::Mesh(mesh)))
+            },
+            "plymesh" => {
+                let file = params.string("filename").ok_or_else(|| ImportError::Invalid(String::from("plymesh without 'filename'")))?;
+                Ok(Some(Surface::Mesh(ply::load(self.path(file))?)))
+            },
+            _ => {
+                self.warnings.push(format!("Shape '{ty}' not supported, ignored"));
+                Ok(None)
+            },
+        }
+    }
+
+    fn finish(mut self) -> Result<Scene, ImportError> {
+        if !self.attribute_stack.is_empty() || self.current_object.is_some() {
+            self.warnings.push(String::from("Missing AttributeEnd or ObjectEnd at the end of the file"));
+        }
+        let (ty, params) = match self.camera.take() {
+            Some((ty, params)) if ["perspective", "orthographic", "spherical", "realistic"].contains(&ty.as_str()) => (ty, params),
+            Some((ty, _)) => {
+                self.warnings.push(format!("Camera '{ty}' not supported, replaced by a perspective one"));
+                (String::from("perspective"), Params { list: vec![] })
+            },
+            None => (String::from("perspective"), Params { list: vec![] }),
+        };
+        if params.get("screenwindow").is_some() || params.get("frameaspectratio").is_some() {
+            self.warnings.push(String::from("Screen window not supported, the film resolution is used"));
+        }
+
+        //The field of view is the one of the shorter side of the image
+        let (width, height) = self.resolution;
+        let aspect_ratio = width as f64/height as f64;
+        let fov = params.float("fov", 90.)?;
+        let vfov = if aspect_ratio >= 1. { fov } else { 2.*((fov.to_radians()/2.).tan()/aspect_ratio).atan().to_degrees() };
+        let lens_radius = params.float("lensradius", 0.)?;
+        let focus_dist = if lens_radius > 0. { params.float("focaldistance", 1e6)? } else { 1. };
+
+        //The cameras truncate the height from the aspect ratio, width/height may fall just under it
+        let film_aspect = width as f64/(height as f64 + 0.5);
+
+        let (origin, lookat) = (Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: 1. });
+        let camera = match ty.as_str() {
+            //The screen window goes from -1 to 1 on the shorter side
+            "orthographic" => Camera::orthographic(origin, lookat, width, film_aspect, if aspect_ratio >= 1. { 2. } else { 2./aspect_ratio }),
+            "spherical" => {
+                if params.string("mapping").unwrap_or("equalarea") != "equirect" {
+                    self.warnings.push(String::from("Only the equirect mapping of the spherical camera is supported"));
+                }
+                if height != width.div_ceil(2) {
+                    self.warnings.push(String::from("A spherical camera makes 2:1 images, the film height is ignored"));
+                }
+                Camera::equirectangular(origin, lookat, width, None)
+            },
+            "realistic" => {
+                let file = params.string("lensfile").ok_or_else(|| ImportError::Invalid(String::from("Realistic camera without 'lensfile'")))?;
+                let mut elements = lens_file(&self.path(file))?;
+                //The stop opens up to its size in the prescription
+                let diameter = params.float("aperturediameter", 1.)?;
+                for stop in elements.iter_mut().filter(|element| element.radius == 0.) {
+                    if diameter > stop.aperture {
+                        self.warnings.push(format!("Aperture diameter {diameter} larger than the lens allows, {} is used", stop.aperture));
+                    }
+                    stop.aperture = diameter.min(stop.aperture);
+                }
+                Camera::realistic(origin, lookat, width, film_aspect, elements, self.film_diagonal, params.float("focusdistance", 10.)?)
+            },
+            _ => Camera::new(origin, lookat, width, film_aspect, vfov, 2.*lens_radius, focus_dist),
+        };
+        self.world.background = Background::Uniform(self.infinite_light);
+
+        Ok(Scene {
+            camera,
+            world : self.world,
+            samples_per_pixel : self.samples_per_pixel,
+            crop_window : self.crop_window,
+            warnings : self.warnings,
+        })
+    }
+}
+
+//Lens prescription with a surface per line : radius, thickness, index of refraction and aperture diameter in millimeters
+fn lens_file(path : &Path) -> Result<Vec<LensElement>, ImportError> {
+    let numbers = fs::read_to_string(path)?.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace)
+        .map(|number| number.parse::<f64>().map_err(|_| ImportError::Invalid(format!("Invalid number '{number}' in the lens file"))))
+        .collect::<Result<Vec<f64>, ImportError>>()?;
+    if numbers.is_empty() || numbers.len()%4 != 0 {
+        return Err(ImportError::Invalid(String::from("A lens file has four numbers per surface")));
+    }
+    Ok(numbers.chunks(4).map(|surface| LensElement::new(surface[0], surface[1], surface[2], surface[3])).collect())
+}
+
+//World to camera transformation of a camera at eye looking at look
+fn look_at(eye : Point<f64>, look : Point<f64>, up : Point<f64>) -> Option<Transform> {
+    let dir = (look - eye).unit();
+    let right = up.unit()^dir;
+    if right.norm() < 1e-9 || !right.norm().is_finite() {
+        return None;
+    }
+    let right = right.unit();
+    let new_up = dir^right;
+    let camera_to_world = Transform::from_columns([
+        [right.x, right.y, right.z, 0.],
+        [new_up.x, new_up.y, new_up.z, 0.],
+        [dir.x, dir.y, dir.z, 0.],
+        [eye.x, eye.y, eye.z, 1.],
+    ])?;
+    Some(camera_to_world.inverse())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    #[test]
+    fn camera_shapes_and_lights() {
+        let scene = parse(r#"
+            # A lit sphere seen from z = 5
+            LookAt 0 0 5  0 0 0  0 1 0
+            Camera "perspective" "float fov" [ 40 ]
+            Film "image" "integer xresolution" [ 300 ] "integer yresolution" [ 200 ] "string filename" "out.exr"
+                "float cropwindow" [ 0.25 0.75 0 0.5 ]
+            Sampler "stratified" "integer xsamples" 4 "integer ysamples" [ 2 ]
+            Integrator "path" "integer maxdepth" [ 5 ]
+            WorldBegin
+            LightSource "infinite" "rgb L" [ 0.1 0.2 0.3 ]
+            LightSource "point" "point from" [ 0 4 0 ]
+            AttributeBegin
+                Material "metal" "float roughness" 0.2
+                Translate 0 0 -1
+                Shape "sphere" "float radius" 1
+            AttributeEnd
+            AttributeBegin
+                AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+                Shape "trianglemesh" "integer indices" [ 0 1 2 ] "point P" [ -1 3 -1  1 3 -1  0 3 1 ]
+            AttributeEnd
+            WorldEnd
+        "#, None, WorkingSpace::Rec709).unwrap();
+
+        assert_eq!(scene.samples_per_pixel, 8);
+        assert_eq!((scene.camera.image_width, scene.camera.image_height), (300, 200));
+        assert_eq!(scene.crop_window, Some(CropWindow::new(75, 0, 150, 100)));
+        assert_eq!(scene.warnings.len(), 2);
+        assert_eq!(scene.world.objects.len(), 2);
+        assert!(matches!(scene.world.background, Background::Uniform(Color { b, .. }) if b == 0.3));
+
+        //The sphere is 6 units in front of the camera
+        let ray = Ray::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: 1. }, 0);
+        let (hit, _, texture) = ray.object_hitted(&scene.world).expect("The sphere should be in front of the camera");
+        assert!((hit.t - 5.).abs() < 1e-9);
+        assert!(matches!(texture.as_ref(), Texture::Metal(_)));
+
+        //The light is above it, y is still up in camera space
+        let ray = Ray::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 3., z: 5. }, 0);
+        let (_, _, texture) = ray.object_hitted(&scene.world).expect("The light should be above the sphere");
+        assert!(matches!(texture.as_ref(), Texture::Emissive(_)));
+
+        //The colors are moved to the working space, the scene keeps it
+        let scene = parse(r#"LightSource "infinite" "rgb L" [ 0.1 0.2 0.3 ]"#, None, WorkingSpace::AcesCg).unwrap();
+        let light = WorkingSpace::AcesCg.from_rec709(Color { r: 0.1, g: 0.2, b: 0.3 });
+        assert_eq!(scene.world.working_space, WorkingSpace::AcesCg);
+        assert!(matches!(scene.world.background, Background::Uniform(color) if (color.r, color.g, color.b) == (light.r, light.g, light.b)));
+    }
+
+    #[test]
+    fn malformed_files() {
+        assert!(matches!(parse(r#"Shape "sphere "#, None, WorkingSpace::Rec709), Err(ImportError::Truncated(_))));
+        assert!(matches!(parse(r#"Shape "trianglemesh" "integer indices" [ 0 1 3 ] "point P" [ 0 0 0 1 0 0 0 1 0 ]"#, None, WorkingSpace::Rec709), Err(ImportError::Invalid(_))));
+        assert!(matches!(parse("Translate 1 2", None, WorkingSpace::Rec709), Err(ImportError::Truncated(_))));
+
+        //Files including each other, and the same one twice in a row
+        let dir = std::env::temp_dir().join(format!("pbrt_include_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.pbrt"), r#"Include "b.pbrt""#).unwrap();
+        fs::write(dir.join("b.pbrt"), r#"Translate 1 2 3 Include "a.pbrt""#).unwrap();
+        fs::write(dir.join("c.pbrt"), "Translate 1 2 3").unwrap();
+        assert!(matches!(parse(r#"Include "a.pbrt""#, Some(&dir), WorkingSpace::Rec709), Err(ImportError::Invalid(_))));
+        assert!(parse(r#"Include "c.pbrt" Include "c.pbrt""#, Some(&dir), WorkingSpace::Rec709).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use std::{fs, path::Path, str::SplitAsciiWhitespace};
+
+use crate::{point::Point, color::Color, world::mesh::Mesh};
+use super::ImportError;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name : &str) -> Result<Scalar, ImportError> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(ImportError::Invalid(format!("Unknown PLY property type '{name}'"))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    //Value used to normalize a color stored with this type
+    fn color_max(self) -> f64 {
+        match self {
+            Scalar::U8 | Scalar::I8 => 255.,
+            Scalar::U16 | Scalar::I16 => 65535.,
+            Scalar::U32 | Scalar::I32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.,
+        }
+    }
+}
+
+enum Property {
+    Scalar { name : String, ty : Scalar },
+    List { name : String, count_ty : Scalar, item_ty : Scalar },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name,
+        }
+    }
+}
+
+struct Element {
+    name : String,
+    count : usize,
+    properties : Vec<Property>,
+}
+
+//The data following the header
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary { data : &'a [u8], pos : usize, big_endian : bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty : Scalar, element : &str) -> Result<f64, ImportError> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| ImportError::Truncated(format!("Missing values in element '{element}'")))?;
+                token.parse::<f64>().map_err(|_| ImportError::Invalid(format!("'{token}' is not a number in element '{element}'")))
+            },
+            Body::Binary { data, pos, big_endian } => {
+                let size = ty.size();
+                if *pos + size > data.len() {
+                    return Err(ImportError::Truncated(format!("Missing bytes in element '{element}'")));
+                }
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&data[*pos..*pos + size]);
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                *pos += size;
+                Ok(match ty {
+                    Scalar::I8 => bytes[0] as i8 as f64,
+                    Scalar::U8 => bytes[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(bytes),
+                })
+            },
+        }
+    }
+
+    //Read a list and return its items
+    fn read_list(&mut self, count_ty : Scalar, item_ty : Scalar, element : &str) -> Result<Vec<f64>, ImportError> {
+        let count = self.read(count_ty, element)?;
+        if count < 0. || count.fract() != 0. {
+            return Err(ImportError::Invalid(format!("Invalid list size {count} in element '{element}'")));
+        }
+        (0..count as usize).map(|_| self.read(item_ty, element)).collect()
+    }
+}
+
+//Load a PLY mesh (ascii or binary, little or big endian)
+pub fn load<P : AsRef<Path>>(path : P) -> Result<Mesh, ImportError> {
+    parse(&fs::read(path)?)
+}
+
+pub fn parse(data : &[u8]) -> Result<Mesh, ImportError> {
+    let (format, elements, body_start) = parse_header(data)?;
+    let body = &data[body_start..];
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(std::str::from_utf8(body).map_err(|_| ImportError::Invalid(String::from("Non ascii data in an ascii PLY")))?.split_ascii_whitespace()),
+        _ => Body::Binary { data: body, pos: 0, big_endian: format == Format::BinaryBigEndian },
+    };
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut has_normals = false;
+    let mut has_colors = false;
+    let mut triangles = vec![];
+
+    for element in &elements {
+        let find = |name : &str| element.properties.iter().position(|p| p.name() == name);
+        match element.name.as_str() {
+            "vertex" => {
+                let (x, y, z) = match (find("x"), find("y"), find("z")) {
+                    (Some(x), Some(y), Some(z)) => (x, y, z),
+                    _ => return Err(ImportError::Invalid(String::from("The vertices have no x, y, z properties"))),
+                };
+                let normal = match (find("nx"), find("ny"), find("nz")) {
+                    (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+                    _ => None,
+                };
+                let color = match (find("red"), find("green"), find("blue")) {
+                    (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+                    _ => None,
+                };
+                has_normals = normal.is_some();
+                has_colors = color.is_some();
+
+                let mut values = vec![0.; element.properties.len()];
+                for _ in 0..element.count {
+                    for (k, property) in element.properties.iter().enumerate() {
+                        match property {
+                            Property::Scalar { ty, .. } => {
+                                values[k] = body.read(*ty, &element.name)?;
+                                if color.is_some_and(|(r, g, b)| k == r || k == g || k == b) {
+                                    values[k] /= ty.color_max();
+                                }
+                            },
+                            Property::List { count_ty, item_ty, .. } => {
+                                body.read_list(*count_ty, *item_ty, &element.name)?;
+                            },
+                        }
+                    }
+                    vertices.push(Point { x: values[x], y: values[y], z: values[z] });
+                    if let Some((x, y, z)) = normal {
+                        normals.push(Point { x: values[x], y: values[y], z: values[z] });
+                    }
+                    if let Some((r, g, b)) = color {
+                        colors.push(Color { r: values[r], g: values[g], b: values[b] });
+                    }
+                }
+            },
+            "face" => {
+                let indices = find("vertex_indices").or(find("vertex_index"))
+                    .ok_or_else(|| ImportError::Invalid(String::from("The faces have no vertex_indices property")))?;
+                for _ in 0..element.count {
+                    for (k, property) in element.properties.iter().enumerate() {
+                        match property {
+                            Property::Scalar { ty, .. } => {
+                                body.read(*ty, &element.name)?;
+                            },
+                            Property::List { count_ty, item_ty, .. } => {
+                                let list = body.read_list(*count_ty, *item_ty, &element.name)?;
+                                if k != indices {
+                                    continue;
+                                }
+                                if list.len() < 3 {
+                                    return Err(ImportError::Invalid(format!("A face has only {} vertices", list.len())));
+                                }
+                                //The values are read as floats, a negative or fractional index would be cast to a wrong vertex
+                                //The upper bound is checked once all the vertices are read
+                                let list = list.into_iter().map(|i| if i >= 0. && i.fract() == 0. {
+                                    Ok(i as usize)
+                                } else {
+                                    Err(ImportError::Invalid(format!("Invalid vertex index {i} in a face")))
+                                }).collect::<Result<Vec<usize>, ImportError>>()?;
+                                //Triangulate the polygon as a fan
+                                for i in 1..list.len() - 1 {
+                                    triangles.push([list[0], list[i], list[i + 1]]);
+                                }
+                            },
+                        }
+                    }
+                }
+            },
+            _ => {
+                //Skip the elements we don't use
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property {
+                            Property::Scalar { ty, .. } => { body.read(*ty, &element.name)?; },
+                            Property::List { count_ty, item_ty, .. } => { body.read_list(*count_ty, *item_ty, &element.name)?; },
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    if triangles.is_empty() {
+        return Err(ImportError::Invalid(String::from("The file contains no face")));
+    }
+    if let Some(index) = triangles.iter().flatten().find(|&&i| i >= vertices.len()) {
+        return Err(ImportError::Invalid(format!("A face uses the vertex {index} but there are only {} vertices", vertices.len())));
+    }
+
+    Ok(Mesh::new(
+        vertices,
+        triangles,
+        if has_normals { Some(normals) } else { None },
+        if has_colors { Some(colors) } else { None },
+    ))
+}
+
+//Parse the header, return the format, the elements and the position of the body
+fn parse_header(data : &[u8]) -> Result<(Format, Vec<Element>, usize), ImportError> {
+    if !data.starts_with(b"ply") {
+        return Err(ImportError::Invalid(String::from("Missing 'ply' magic number")));
+    }
+    let end = data.windows(10).position(|w| w == b"end_header")
+        .ok_or_else(|| ImportError::Truncated(String::from("Missing 'end_header'")))?;
+    let body_start = match data[end + 10..].iter().position(|&b| b == b'\n') {
+        Some(offset) => end + 10 + offset + 1,
+        None => data.len(),
+    };
+    let header = std::str::from_utf8(&data[..end]).map_err(|_| ImportError::Invalid(String::from("Non ascii header")))?;
+
+    let mut format = None;
+    let mut elements : Vec<Element> = vec![];
+    for line in header.lines().skip(1) {
+        let words : Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => (),
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(ImportError::Unsupported(format!("PLY format '{name}'"))),
+                });
+            },
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| ImportError::Invalid(format!("Invalid count for element '{name}'")))?;
+                elements.push(Element { name: name.to_string(), count, properties: vec![] });
+            },
+            ["property", "list", count_ty, item_ty, name] => {
+                let element = elements.last_mut().ok_or_else(|| ImportError::Invalid(String::from("Property declared before any element")))?;
+                element.properties.push(Property::List { name: name.to_string(), count_ty: Scalar::parse(count_ty)?, item_ty: Scalar::parse(item_ty)? });
+            },
+            ["property", ty, name] => {
+                let element = elements.last_mut().ok_or_else(|| ImportError::Invalid(String::from("Property declared before any element")))?;
+                element.properties.push(Property::Scalar { name: name.to_string(), ty: Scalar::parse(ty)? });
+            },
+            _ => return Err(ImportError::Invalid(format!("Unexpected header line '{line}'"))),
+        }
+    }
+
+    let format = format.ok_or_else(|| ImportError::Invalid(String::from("Missing 'format' line")))?;
+    Ok((format, elements, body_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER : &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn binary(big_endian : bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut data = format!("ply\nformat {format} 1.0\n{HEADER}").into_bytes();
+        for v in [0f32, 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 0.] {
+            data.extend_from_slice(&if big_endian { v.to_be_bytes() } else { v.to_le_bytes() });
+        }
+        data.push(4);
+        for i in [0i32, 1, 2, 3] {
+            data.extend_from_slice(&if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+        }
+        data
+    }
+
+    #[test]
+    fn ascii_with_normals_and_colors() {
+        let data = "ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 0 0 1 255 0 0\n1 0 0 0 0 1 0 255 0\n1 1 0 0 0 1 0 0 255\n0 1 0 0 0 1 51 51 51\n4 0 1 2 3\n";
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.normals().unwrap()[2].z, 1.);
+        let color = mesh.colors().unwrap()[3];
+        assert!((color.r - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn binary_both_endianness() {
+        for big_endian in [false, true] {
+            let mesh = parse(&binary(big_endian)).unwrap();
+            assert_eq!(mesh.vertices().len(), 4);
+            assert_eq!(mesh.vertices()[2].y, 1.);
+            assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+            assert!(mesh.normals().is_none());
+        }
+    }
+
+    #[test]
+    fn truncated_and_inconsistent_files() {
+        let data = binary(false);
+        assert!(matches!(parse(&data[..data.len() - 2]), Err(ImportError::Truncated(_))));
+        assert!(matches!(parse(&data[..40]), Err(ImportError::Truncated(_))));
+
+        let bad_index = format!("ply\nformat ascii 1.0\n{HEADER}0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 7\n");
+        assert!(matches!(parse(bad_index.as_bytes()), Err(ImportError::Invalid(_))));
+        let negative_index = format!("ply\nformat ascii 1.0\n{HEADER}0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 -1 2\n");
+        assert!(matches!(parse(negative_index.as_bytes()), Err(ImportError::Invalid(_))));
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fs, path::Path};
+
+use crate::{point::Point, world::mesh::Mesh};
+use super::ImportError;
+
+//Load a STL mesh (ascii or binary)
+//The normals stored in the file are ignored, the facets are shaded flat
+pub fn load<P : AsRef<Path>>(path : P) -> Result<Mesh, ImportError> {
+    parse(&fs::read(path)?)
+}
+
+pub fn parse(data : &[u8]) -> Result<Mesh, ImportError> {
+    //A binary file can also begin with "solid", so we trust the announced size first
+    if data.len() >= 84 {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if data.len() == 84 + 50*count {
+            return parse_binary(data);
+        }
+    }
+    if data.starts_with(b"solid") && std::str::from_utf8(data).is_ok() {
+        parse_ascii(data)
+    }
+    else {
+        parse_binary(data)
+    }
+}
+
+fn parse_binary(data : &[u8]) -> Result<Mesh, ImportError> {
+    if data.len() < 84 {
+        return Err(ImportError::Truncated(String::from("Binary STL header is shorter than 84 bytes")));
+    }
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    if count == 0 {
+        return Err(ImportError::Invalid(String::from("The file contains no facet")));
+    }
+    if data.len() < 84 + 50*count {
+        return Err(ImportError::Truncated(format!("{count} facets announced but only {} present", (data.len() - 84)/50)));
+    }
+
+    let float = |pos : usize| f32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as f64;
+    let mut vertices = Vec::with_capacity(3*count);
+    for facet in 0..count {
+        //Skip the normal (12 bytes), read the 3 vertices, skip the attribute (2 bytes)
+        let start = 84 + 50*facet + 12;
+        for corner in 0..3 {
+            let pos = start + 12*corner;
+            vertices.push(Point { x: float(pos), y: float(pos + 4), z: float(pos + 8) });
+        }
+    }
+    Ok(build(vertices))
+}
+
+fn parse_ascii(data : &[u8]) -> Result<Mesh, ImportError> {
+    let text = std::str::from_utf8(data).map_err(|_| ImportError::Invalid(String::from("Non ascii data in an ascii STL")))?;
+    let mut tokens = text.split_ascii_whitespace();
+    let mut vertices = vec![];
+    let mut in_facet = 0;
+    let mut ended = false;
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "vertex" => {
+                let mut coordinate = || -> Result<f64, ImportError> {
+                    let token = tokens.next().ok_or_else(|| ImportError::Truncated(String::from("Missing vertex coordinates")))?;
+                    token.parse().map_err(|_| ImportError::Invalid(format!("'{token}' is not a number")))
+                };
+                vertices.push(Point { x: coordinate()?, y: coordinate()?, z: coordinate()? });
+                in_facet += 1;
+            },
+            "facet" => in_facet = 0,
+            "endfacet" if in_facet != 3 => {
+                return Err(ImportError::Invalid(format!("A facet has {in_facet} vertices instead of 3")));
+            },
+            "endsolid" => {
+                ended = true;
+                break;
+            },
+            _ => (),
+        }
+    }
+
+    if !ended {
+        return Err(ImportError::Truncated(String::from("Missing 'endsolid'")));
+    }
+    if vertices.is_empty() || vertices.len()%3 != 0 {
+        return Err(ImportError::Invalid(String::from("The facets are incomplete")));
+    }
+    Ok(build(vertices))
+}
+
+//Each facet has its own 3 vertices
+fn build(vertices : Vec<Point<f64>>) -> Mesh {
+    let triangles = (0..vertices.len()/3).map(|i| [3*i, 3*i + 1, 3*i + 2]).collect();
+    Mesh::new(vertices, triangles, None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_and_binary() {
+        let ascii = b"solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid test\n";
+        let mesh = parse(ascii).unwrap();
+        assert_eq!(mesh.triangles().len(), 1);
+        assert_eq!(mesh.vertices()[1].x, 1.);
+
+        //Binary header starting with "solid" as some exporters do
+        let mut binary = b"solid exported".to_vec();
+        binary.resize(80, 0);
+        binary.extend_from_slice(&2u32.to_le_bytes());
+        for _ in 0..2 {
+            for v in [0f32, 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+                binary.extend_from_slice(&v.to_le_bytes());
+            }
+            binary.extend_from_slice(&[0, 0]);
+        }
+        let mesh = parse(&binary).unwrap();
+        assert_eq!(mesh.triangles().len(), 2);
+        assert_eq!(mesh.vertices()[5].y, 1.);
+
+        assert!(matches!(parse(&binary[..binary.len() - 10]), Err(ImportError::Truncated(_))));
+        assert!(matches!(parse(&ascii[..ascii.len() - 15]), Err(ImportError::Truncated(_))));
+    }
+}
@@ -1,13 +1,168 @@
-use ray_tracing_we;
-use std::time::{Instant, Duration};
+use std::{env, process, time::Instant};
 
-fn main() {
-    let now = Instant::now();
-    ray_tracing_we::run();
-    eprintln!("This render took : {} ms", now.elapsed().as_millis());
+use ray_tracing_we::{scene1, scene2, scene3, Camera, camera::stereo::{Stereo, Rig}, world::World, color::display::{Display, ToneMapper}, sampler::SamplerKind, render::{self, Adaptive, CropWindow, Framebuffer, Layout, filter::Filter, denoise::{self, Denoise, Features}, distributed::{self, Slice}}};
+
+const USAGE : &str = "Usage :
+    ray_tracing_we
+    ray_tracing_we slice <scene1|scene2|scene3> <width> <x> <y> <slice width> <slice height> <first sample> <samples> <seed> <output> [independent|stratified|halton|sobol]
+    ray_tracing_we merge <output.ppm> <partial>...
+    ray_tracing_we adaptive <scene1|scene2|scene3> <width> <threshold> <max samples> <output.ppm> [heatmap.ppm]
+    ray_tracing_we preview <scene1|scene2|scene3> <width> <samples> <output.ppm> [clamp|reinhard|aces|agx] [exposure]
+    ray_tracing_we aovs <scene1|scene2|scene3> <width> <samples> <prefix>
+    ray_tracing_we stereo <scene1|scene2|scene3> <width> <samples> <interaxial> <convergence> <parallel|off-axis> <side-by-side|top-bottom|separate> <output.ppm|prefix>
+    ray_tracing_we crop <scene1|scene2|scene3> <width> <samples> <x> <y> <crop width> <crop height> <output.ppm> [cropped|full]
+    ray_tracing_we rgba <scene1|scene2|scene3> <width> <samples> <output.png|output.exr> [visible|lighting-only]";
+
+fn fail(message : &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1)
 }
 
+fn number<T : std::str::FromStr>(args : &[String], index : usize) -> T {
+    args[index].parse().unwrap_or_else(|_| fail(&format!("'{}' is not a valid number\n{USAGE}", args[index])))
+}
 
+fn scene(name : &str, width : u32) -> (Camera, World) {
+    match name {
+        "scene1" => scene1(width),
+        "scene2" => scene2(width, 0),
+        "scene3" => scene3(width),
+        _ => fail(&format!("Unknown scene '{name}'\n{USAGE}")),
+    }
+}
 
+fn sampler(name : &str) -> SamplerKind {
+    match name {
+        "independent" => SamplerKind::Independent,
+        "stratified" => SamplerKind::Stratified,
+        "halton" => SamplerKind::Halton,
+        "sobol" => SamplerKind::Sobol,
+        _ => fail(&format!("Unknown sampler '{name}'\n{USAGE}")),
+    }
+}
 
+fn tone_mapper(name : &str) -> ToneMapper {
+    match name {
+        "clamp" => ToneMapper::Clamp,
+        "reinhard" => ToneMapper::Reinhard,
+        "aces" => ToneMapper::Aces,
+        "agx" => ToneMapper::Agx,
+        _ => fail(&format!("Unknown tone mapper '{name}'\n{USAGE}")),
+    }
+}
 
+fn rig(name : &str) -> Rig {
+    match name {
+        "parallel" => Rig::Parallel,
+        "off-axis" => Rig::OffAxis,
+        _ => fail(&format!("Unknown stereo rig '{name}'\n{USAGE}")),
+    }
+}
+
+fn main() {
+    let now = Instant::now();
+    let args : Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        None => ray_tracing_we::run(),
+        //Render a part of the image to a file, for a merge later
+        Some("slice") if args.len() == 12 || args.len() == 13 => {
+            let (camera, world) = scene(&args[2], number(&args, 3));
+            let slice = Slice::new(number(&args, 4), number(&args, 5), number(&args, 6), number(&args, 7), number(&args, 8), number(&args, 9), number(&args, 10));
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let sampler = args.get(12).map_or(SamplerKind::Sobol, |name| sampler(name));
+            let partial = distributed::render_slice(&camera, &world, args[2].as_bytes(), threads, sampler, Filter::Box(0.5), &slice).unwrap_or_else(|err| fail(&err.to_string()));
+            partial.save(&args[11]).unwrap_or_else(|err| fail(&format!("Couldn't write {} : {err}", args[11])));
+        },
+        Some("merge") if args.len() >= 4 => {
+            let (framebuffer, working_space) = distributed::merge_files(&args[3..]).unwrap_or_else(|err| fail(&err.to_string()));
+            if framebuffer.min_samples() == 0 {
+                eprintln!("Warning : some pixels have no sample");
+            }
+            framebuffer.save_ppm(&args[2], &Display::default().in_space(working_space)).unwrap_or_else(|err| fail(&format!("Couldn't write {} : {err}", args[2])));
+        },
+        //More samples where the image is noisy
+        Some("adaptive") if args.len() == 7 || args.len() == 8 => {
+            let (camera, world) = scene(&args[2], number(&args, 3));
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let framebuffer = render::render_adaptive(&camera, &world, threads, &Adaptive::new(number(&args, 4), number(&args, 5)));
+            framebuffer.save_ppm(&args[6], &Display::default().in_space(world.working_space)).unwrap_or_else(|err| fail(&format!("Couldn't write {} : {err}", args[6])));
+            if let Some(path) = args.get(7) {
+                framebuffer.save_heatmap(path).unwrap_or_else(|err| fail(&format!("Couldn't write {path} : {err}")));
+            }
+        },
+        //Few samples then the denoiser
+        Some("preview") if (6..=8).contains(&args.len()) => {
+            let (camera, world) = scene(&args[2], number(&args, 3));
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let (framebuffer, aovs) = render::render_aovs(&camera, &world, number(&args, 4), threads, SamplerKind::Sobol, Filter::Box(0.5), 0);
+            let denoised = denoise::denoise(&framebuffer, &Features::new(&aovs), &Denoise::default(), threads);
+            let display = Display::new(if args.len() == 8 { number(&args, 7) } else { 0. }, args.get(6).map_or(ToneMapper::Clamp, |name| tone_mapper(name))).in_space(world.working_space);
+            denoised.save_ppm(&args[5], &display).unwrap_or_else(|err| fail(&format!("Couldn't write {} : {err}", args[5])));
+        },
+        //The image and its auxiliary buffers, as prefix.<layer>.pfm
+        Some("aovs") if args.len() == 6 => {
+            let (camera, world) = scene(&args[2], number(&args, 3));
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let (framebuffer, aovs) = render::render_aovs(&camera, &world, number(&args, 4), threads, SamplerKind::Sobol, Filter::Box(0.5), 0);
+            aovs.save(&args[5], &framebuffer).unwrap_or_else(|err| fail(&format!("Couldn't write the buffers of {} : {err}", args[5])));
+        },
+        //Both eyes in one image, or as prefix.left.ppm and prefix.right.ppm
+        Some("stereo") if args.len() == 10 => {
+            let (camera, world) = scene(&args[2], number(&args, 3));
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let stereo = Stereo::new(number(&args, 5), number(&args, 6), rig(&args[7]));
+            let layout = match args[8].as_str() {
+                "side-by-side" => Some(Layout::SideBySide),
+                "top-bottom" => Some(Layout::TopBottom),
+                "separate" => None,
+                layout => fail(&format!("Unknown stereo layout '{layout}'\n{USAGE}")),
+            };
+            let (left, right) = render::render_stereo(&camera, &world, &stereo, number(&args, 4), threads, SamplerKind::Sobol, Filter::Box(0.5), 0);
+            let save = |framebuffer : &Framebuffer, path : &str| framebuffer.save_ppm(path, &Display::default().in_space(world.working_space)).unwrap_or_else(|err| fail(&format!("Couldn't write {path} : {err}")));
+            match layout {
+                Some(layout) => save(&Framebuffer::pack(&left, &right, layout), &args[9]),
+                None => {
+                    save(&left, &format!("{}.left.ppm", args[9]));
+                    save(&right, &format!("{}.right.ppm", args[9]));
+                },
+            }
+        },
+        //A rectangle of the image alone, or the whole image black around it
+        Some("crop") if args.len() == 10 || args.len() == 11 => {
+            let (camera, world) = scene(&args[2], number(&args, 3));
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let window = CropWindow::new(number(&args, 5), number(&args, 6), number(&args, 7), number(&args, 8));
+            let cropped = match args.get(10).map_or("cropped", String::as_str) {
+                "cropped" => true,
+                "full" => false,
+                output => fail(&format!("Unknown crop output '{output}'\n{USAGE}")),
+            };
+            let framebuffer = render::render_crop(&camera, &world, &window, number(&args, 4), threads, SamplerKind::Sobol, Filter::Box(0.5), 0);
+            let framebuffer = if cropped { framebuffer.crop(&window) } else { framebuffer };
+            framebuffer.save_ppm(&args[9], &Display::default().in_space(world.working_space)).unwrap_or_else(|err| fail(&format!("Couldn't write {} : {err}", args[9])));
+        },
+        //With an alpha channel, the background can light the scene without being seen
+        Some("rgba") if args.len() == 6 || args.len() == 7 => {
+            let (camera, mut world) = scene(&args[2], number(&args, 3));
+            world.background_visible = match args.get(6).map_or("visible", String::as_str) {
+                "visible" => true,
+                "lighting-only" => false,
+                background => fail(&format!("Unknown background option '{background}'\n{USAGE}")),
+            };
+            let path = &args[5];
+            let exr = match path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()) {
+                Some(extension) if extension == "exr" => true,
+                Some(extension) if extension == "png" => false,
+                _ => fail(&format!("The output of rgba is a .png or .exr file\n{USAGE}")),
+            };
+            let threads = std::thread::available_parallelism().map_or(8, |threads| threads.get());
+            let framebuffer = render::render(&camera, &world, number(&args, 4), threads, SamplerKind::Sobol, Filter::Box(0.5), 0);
+            let saved = if exr { framebuffer.save_exr(path) } else { framebuffer.save_png(path, &Display::default().in_space(world.working_space)) };
+            saved.unwrap_or_else(|err| fail(&format!("Couldn't write {path} : {err}")));
+        },
+        _ => fail(USAGE),
+    }
+
+    eprintln!("This render took : {} ms", now.elapsed().as_millis());
+}
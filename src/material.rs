@@ -1,18 +1,52 @@
-use crate::{ray::Ray, world::Record, color::Color};
+use crate::{ray::Ray, world::Record, color::Color, sampler::Sampler};
 
-use std::rc::Rc;
 
 pub mod diffuse;
 pub mod metal;
 pub mod dielectric;
+pub mod image;
+pub mod pbr;
+pub mod emissive;
 
 pub trait Material {
-    fn scatter(&self, r_in : Ray, hit : Record) -> (Ray, &Color<f64>);
+    fn scatter(&self, r_in : Ray, hit : Record, sampler : &mut Sampler) -> (Ray, Color<f64>);
+    //Color of the surface without its lighting, for the denoiser
+    fn albedo(&self, hit : &Record) -> Color<f64>;
 }
 
 
+#[derive(Debug)]
 pub enum Texture {
     Diffuse(diffuse::Diffuse),
     Metal(metal::Metal),
     Dielectric(dielectric::Dielectric),
+    Pbr(pbr::Pbr),
+    Emissive(emissive::Emissive),
+}
+
+impl Texture {
+    //None for the lights, they don't reflect anything
+    pub fn scatter(&self, r_in : Ray, hit : Record, sampler : &mut Sampler) -> Option<(Ray, Color<f64>)> {
+        match self {
+            Texture::Diffuse(diffuse) => Some(diffuse.scatter(r_in, hit, sampler)),
+            Texture::Metal(metal) => Some(metal.scatter(r_in, hit, sampler)),
+            Texture::Dielectric(dielectric) => Some(dielectric.scatter(r_in, hit, sampler)),
+            Texture::Pbr(pbr) => Some(pbr.scatter(r_in, hit, sampler)),
+            Texture::Emissive(_) => None,
+        }
+    }
+
+    pub fn albedo(&self, hit : &Record) -> Color<f64> {
+        match self {
+            Texture::Diffuse(diffuse) => diffuse.albedo(hit),
+            Texture::Metal(metal) => metal.albedo(hit),
+            Texture::Dielectric(dielectric) => dielectric.albedo(hit),
+            Texture::Pbr(pbr) => pbr.albedo(hit),
+            //The color of the light, as it is seen directly
+            Texture::Emissive(light) => {
+                let emitted = light.emitted(hit);
+                Color { r: emitted.r.min(1.), g: emitted.g.min(1.), b: emitted.b.min(1.) }
+            },
+        }
+    }
 }
@@ -1,8 +1,9 @@
-use crate::{ray::Ray, world::Record, color::Color, point::Point};
+use crate::{ray::Ray, world::Record, color::Color, point::Point, sampler::Sampler};
 
 use super::Material;
 
 
+#[derive(Debug)]
 pub struct Dielectric {
     eta : f64,
     albedo : Color<f64>,
@@ -25,7 +26,7 @@ impl Dielectric {
     //Calculate the refracted direction
     fn refract(&self, inc_unit : Point<f64>, normal : Point<f64>, eta_quotient : f64, cos_theta : f64) -> Point<f64> {
         let dir_out_perp = (inc_unit + normal*cos_theta)*eta_quotient;
-        let dir_out_parallel = normal * (-1. * (1.-(dir_out_perp.norm_squared())).sqrt());
+        let dir_out_parallel = normal * (-(1.-(dir_out_perp.norm_squared())).sqrt());
         dir_out_parallel + dir_out_perp
     }
 
@@ -40,20 +41,24 @@ impl Dielectric {
 
 impl Material for Dielectric {
     
-    fn scatter(&self, r_in : crate::ray::Ray, hit : crate::world::Record) -> (crate::ray::Ray, &crate::color::Color<f64>) {
+    fn scatter(&self, r_in : crate::ray::Ray, hit : crate::world::Record, sampler : &mut Sampler) -> (crate::ray::Ray, crate::color::Color<f64>) {
         let inc_unit = r_in.dir().unit();
         let eta_quotient = if hit.front_face { 1./self.eta } else { self.eta/1.};
-        let cos_theta = (-1. * (inc_unit&hit.normal)).min(1.);
+        let cos_theta = (-(inc_unit&hit.normal)).min(1.);
         let sin_theta = (1.-cos_theta*cos_theta).sqrt();
 
         //If total reflection or reflectance (applying Schlick Approximation)
-        if (sin_theta*eta_quotient > 1.)||(self.reflectance(cos_theta, eta_quotient) > rand::random() )  {
+        if (sin_theta*eta_quotient > 1.)||(self.reflectance(cos_theta, eta_quotient) > sampler.get_1d())  {
             (self.reflect(r_in, hit),
-            &self.albedo)
+            self.albedo)
         }
         else {
             (Ray::new(hit.p, self.refract(inc_unit, hit.normal, eta_quotient, cos_theta), r_in.get_depth()+1),
-            &self.albedo)
+            self.albedo)
         }
     }
+
+    fn albedo(&self, _hit : &Record) -> Color<f64> {
+        self.albedo
+    }
 }
\ No newline at end of file
@@ -1,9 +1,9 @@
-use crate::{ray::Ray, color::Color, world::Record, point::Point};
+use crate::{ray::Ray, color::Color, world::Record, point::Point, sampler::Sampler};
 
-use std::rc::Rc;
 
 use super::Material;
 
+#[derive(Debug)]
 pub struct Diffuse {
     color : Color<f64>,
 }
@@ -17,12 +17,17 @@ impl Diffuse {
 }
 
 impl Material for Diffuse {
-    fn scatter(&self, r_in : Ray, hit : Record) -> (Ray, &Color<f64>){
-        let mut target = hit.normal + Point::random_unit_in_sphere(1.);
+    fn scatter(&self, r_in : Ray, hit : Record, sampler : &mut Sampler) -> (Ray, Color<f64>){
+        let color = self.albedo(&hit);
+        let mut target = hit.normal + Point::on_unit_sphere(sampler.get_2d());
         if target.near_zero() {
             target = target.unit();
         }
         (Ray::new(hit.p, target, r_in.get_depth()+1),
-        &self.color)
+        color)
+    }
+
+    fn albedo(&self, hit : &Record) -> Color<f64> {
+        self.color*hit.color
     }
 }
\ No newline at end of file
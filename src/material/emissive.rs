@@ -0,0 +1,26 @@
+use crate::{color::Color, world::Record};
+
+//A light emitting surface (pbrt "diffuse" area light), it doesn't reflect anything
+#[derive(Debug)]
+pub struct Emissive {
+    radiance : Color<f64>,
+    two_sided : bool, //Otherwise only the side the outward normal points to emits
+}
+
+impl Emissive {
+    pub fn new(radiance : Color<f64>, two_sided : bool) -> Emissive {
+        Emissive {
+            radiance,
+            two_sided,
+        }
+    }
+
+    pub fn emitted(&self, hit : &Record) -> Color<f64> {
+        if hit.front_face || self.two_sided {
+            self.radiance
+        }
+        else {
+            Color { r: 0., g: 0., b: 0. }
+        }
+    }
+}
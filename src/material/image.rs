@@ -0,0 +1,70 @@
+use crate::color::{Color, display::WorkingSpace};
+
+//An image used to texture a material, stored in linear colors
+#[derive(Debug)]
+pub struct ImageTexture {
+    width : usize,
+    height : usize,
+    pixels : Vec<Color<f64>>,
+}
+
+//Exact sRGB transfer function, from encoded value to linear
+pub fn srgb_to_linear(value : f64) -> f64 {
+    if value <= 0.04045 {
+        value/12.92
+    }
+    else {
+        ((value + 0.055)/1.055).powf(2.4)
+    }
+}
+
+impl ImageTexture {
+    //Pixels are given row by row, starting from the top
+    pub fn new(width : usize, height : usize, pixels : Vec<Color<f64>>) -> ImageTexture {
+        assert!(width > 0 && height > 0, "An image needs at least one pixel");
+        assert_eq!(pixels.len(), width*height, "The number of pixels doesn't match the size of the image");
+        ImageTexture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    //Build from 8 bits sRGB data with the given number of channels per pixel (the extra channels are ignored)
+    pub fn from_srgb8(width : usize, height : usize, data : &[u8], channels : usize) -> ImageTexture {
+        let pixels = data.chunks_exact(channels).map(|p| {
+            let value = |k : usize| srgb_to_linear(p[k.min(channels - 1)] as f64/255.);
+            Color { r: value(0), g: value(1), b: value(2) }
+        }).collect();
+        ImageTexture::new(width, height, pixels)
+    }
+
+    //The images are in Rec.709, the colors are moved to the primaries of the render
+    pub fn in_space(mut self, space : WorkingSpace) -> ImageTexture {
+        for pixel in &mut self.pixels {
+            *pixel = space.from_rec709(*pixel);
+        }
+        self
+    }
+
+    fn texel(&self, i : isize, j : isize) -> Color<f64> {
+        //Repeat the image outside of [0, 1]
+        let i = i.rem_euclid(self.width as isize) as usize;
+        let j = j.rem_euclid(self.height as isize) as usize;
+        self.pixels[j*self.width + i]
+    }
+
+    //Bilinear lookup, v = 0 is the top of the image
+    pub fn sample(&self, (u, v) : (f64, f64)) -> Color<f64> {
+        let x = u*self.width as f64 - 0.5;
+        let y = v*self.height as f64 - 0.5;
+        let (i, j) = (x.floor(), y.floor());
+        let (fx, fy) = (x - i, y - j);
+        let (i, j) = (i as isize, j as isize);
+
+        self.texel(i, j)*((1. - fx)*(1. - fy))
+            + self.texel(i + 1, j)*(fx*(1. - fy))
+            + self.texel(i, j + 1)*((1. - fx)*fy)
+            + self.texel(i + 1, j + 1)*(fx*fy)
+    }
+}
@@ -1,9 +1,10 @@
-use crate::{color::Color, ray::Ray, point::Point};
+use crate::{color::Color, ray::Ray, point::Point, sampler::Sampler};
 
 use super::Material;
 
 
 
+#[derive(Debug)]
 pub struct Metal {
     albedo : Color<f64>,
     fuzzyness : f64,
@@ -19,21 +20,26 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in : crate::ray::Ray, hit : crate::world::Record) -> (crate::ray::Ray, &Color<f64>) {
+    fn scatter(&self, r_in : crate::ray::Ray, hit : crate::world::Record, sampler : &mut Sampler) -> (crate::ray::Ray, Color<f64>) {
         let dir = *(r_in.dir()) - (hit.normal*(hit.normal&*(r_in.dir()))*2.);
+        let albedo = self.albedo(&hit);
         if self.fuzzyness==0. {
             return (Ray::new(hit.p, dir, r_in.get_depth()+1),
-                    &self.albedo);
+                    albedo);
         }
-        let ray = Ray::new(hit.p, dir + Point::random_in_sphere(self.fuzzyness), r_in.get_depth()+1);
+        let ray = Ray::new(hit.p, dir + Point::in_sphere(self.fuzzyness, sampler.get_2d(), sampler.get_1d()), r_in.get_depth()+1);
         //Test if we didn't launched the fuzzed ray into the object
         if *(ray.dir())&hit.normal > 0. {
             (ray,
-            &self.albedo)
+            albedo)
         }
         else {
             (ray,
-            &Color {r: 0., g: 0., b: 0.})
+            Color {r: 0., g: 0., b: 0.})
         }
     }
+
+    fn albedo(&self, hit : &crate::world::Record) -> Color<f64> {
+        self.albedo*hit.color
+    }
 }
\ No newline at end of file
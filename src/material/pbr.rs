@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use crate::{ray::Ray, world::Record, color::Color, point::Point, sampler::Sampler};
+
+use super::{Material, image::ImageTexture};
+
+//Metallic-roughness material (as in glTF)
+//A metal reflects with its base color, a non metal is a diffuse base under a white specular coat
+#[derive(Debug)]
+pub struct Pbr {
+    base_color : Color<f64>,
+    base_color_texture : Option<Arc<ImageTexture>>,
+    metallic : f64,
+    roughness : f64,
+}
+
+//Reflectance at normal incidence of the non metals
+const DIELECTRIC_F0 : f64 = 0.04;
+
+impl Pbr {
+    pub fn new(base_color : Color<f64>, base_color_texture : Option<Arc<ImageTexture>>, metallic : f64, roughness : f64) -> Self {
+        Pbr {
+            base_color,
+            base_color_texture,
+            metallic : metallic.clamp(0., 1.),
+            roughness : roughness.clamp(0., 1.),
+        }
+    }
+
+    //Glossy reflection, the roughness fuzzes the mirror direction
+    fn reflect(&self, r_in : &Ray, hit : &Record, fuzz : ((f64, f64), f64)) -> Option<Ray> {
+        let dir = r_in.dir().unit();
+        let reflected = dir - hit.normal*(hit.normal&dir)*2.;
+        let ray = Ray::new(hit.p, reflected + Point::in_sphere(self.roughness, fuzz.0, fuzz.1), r_in.get_depth()+1);
+        //The fuzzed ray may go into the surface
+        if *(ray.dir())&hit.normal > 0. { Some(ray) } else { None }
+    }
+}
+
+impl Material for Pbr {
+    fn scatter(&self, r_in : Ray, hit : Record, sampler : &mut Sampler) -> (Ray, Color<f64>) {
+        let albedo = self.albedo(&hit);
+        let black = Color { r: 0., g: 0., b: 0. };
+        //Every number is drawn first so each one keeps its dimension whatever the lobe, the 6 of a vertex
+        let lobe = sampler.get_1d();
+        let fuzz = (sampler.get_2d(), sampler.get_1d());
+        let bounce = sampler.get_2d();
+
+        if lobe < self.metallic {
+            return match self.reflect(&r_in, &hit, fuzz) {
+                Some(ray) => (ray, albedo),
+                None => (Ray::new(hit.p, hit.normal, r_in.get_depth()+1), black),
+            };
+        }
+
+        //Schlick approximation of the specular coat
+        let cos_theta = (-(r_in.dir().unit()&hit.normal)).clamp(0., 1.);
+        let fresnel = DIELECTRIC_F0 + (1. - DIELECTRIC_F0)*(1. - cos_theta).powi(5);
+        //The rest of the number past the metal is uniform again, it picks the coat or the base
+        if (lobe - self.metallic)/(1. - self.metallic) < fresnel {
+            if let Some(ray) = self.reflect(&r_in, &hit, fuzz) {
+                return (ray, Color { r: 1., g: 1., b: 1. });
+            }
+        }
+
+        let mut target = hit.normal + Point::on_unit_sphere(bounce);
+        if target.near_zero() {
+            target = hit.normal;
+        }
+        (Ray::new(hit.p, target, r_in.get_depth()+1), albedo)
+    }
+
+    fn albedo(&self, hit : &Record) -> Color<f64> {
+        //The vertex colors multiply the base color as COLOR_0 in glTF
+        match &self.base_color_texture {
+            Some(texture) => self.base_color*texture.sample(hit.uv)*hit.color,
+            None => self.base_color*hit.color,
+        }
+    }
+}
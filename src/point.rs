@@ -9,42 +9,41 @@ pub struct Point<T : Copy> {
 }
 
 impl Point<f64> {
-    pub fn random(min : f64, max : f64) -> Point<f64> {
-        Point {
-            x: (rand::random::<f64>()*(max-min))+min,
-            y: (rand::random::<f64>()*(max-min))+min,
-            z: (rand::random::<f64>()*(max-min))+min,
+    //Uniform in the disk of the plane z = 0, the samples are numbers in [0, 1) given by the sampler
+    //Concentric mapping of Shirley and Chiu, so the strata of the square stay close together in the disk
+    pub fn in_circle(radius : f64, (u, v) : (f64, f64)) -> Point<f64> {
+        let (a, b) = (2.*u - 1., 2.*v - 1.);
+        if a == 0. && b == 0. {
+            return Point { x: 0., y: 0., z: 0. };
         }
+        let quarter = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() { (a, quarter*b/a) } else { (b, 2.*quarter - quarter*a/b) };
+        Point { x: r*theta.cos(), y: r*theta.sin(), z: 0. } * radius
     }
 
-    pub fn random_in_circle(radius : f64) -> Point<f64> {
-        Point { x: rand::random::<f64>()*2.-1.,
-            y: rand::random::<f64>()*2.-1.,
-            z: 0. } * radius
+    //Uniform on the sphere of radius 1
+    pub fn on_unit_sphere((u, v) : (f64, f64)) -> Point<f64> {
+        let z = 1. - 2.*u;
+        let r = (1. - z*z).max(0.).sqrt();
+        let phi = 2.*std::f64::consts::PI*v;
+        Point { x: r*phi.cos(), y: r*phi.sin(), z }
     }
 
-    pub fn random_in_sphere(radius : f64) -> Point<f64> {
-        loop {
-            let p = Point::random(-radius, radius);
-            if p.norm_squared() >= radius {
-                continue;
-            }
-            return p;
-        }
-    }
-    pub fn random_unit_in_sphere(radius : f64) -> Point<f64> {
-        Point::random_in_sphere(radius).unit()
+    //Uniform in the ball, distance picks how far from the center
+    pub fn in_sphere(radius : f64, direction : (f64, f64), distance : f64) -> Point<f64> {
+        Point::on_unit_sphere(direction)*(radius*distance.cbrt())
     }
+
     pub fn unit(&self) -> Point<f64> {
         (*self)/(self.norm())
     }
 
     pub fn norm_squared(&self) -> f64 {
-        (*self)&(*self)
+        self.x*self.x + self.y*self.y + self.z*self.z
     }
 
     pub fn norm(&self) -> f64 {
-        ((*self)&(*self)).sqrt()
+        self.norm_squared().sqrt()
     }
 
     pub fn near_zero(&self) -> bool {
@@ -152,6 +151,17 @@ mod test {
 
     #[test]
     pub fn test_random() {
-        
+        //The whole disk and nothing outside, the same density everywhere
+        let (mut inside, mut count) = (0, 0);
+        for i in 0..64 {
+            for j in 0..64 {
+                let p = Point::in_circle(2., ((i as f64 + 0.5)/64., (j as f64 + 0.5)/64.));
+                assert!(p.norm() <= 2. && p.z == 0.);
+                inside += (p.norm() < 2./2f64.sqrt()) as u32;
+                count += 1;
+            }
+        }
+        assert!((inside as f64/count as f64 - 0.5).abs() < 0.02);
+        assert!((Point::in_circle(1., (0.999999, 0.999999)).norm() - 1.).abs() < 1e-5);
     }
 }
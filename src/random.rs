@@ -0,0 +1,47 @@
+use rand::{RngCore, Error};
+
+//Seedable generator (splitmix64) for what isn't sampled per pixel, like the layout of a scene
+#[derive(Debug, Clone)]
+pub struct Random {
+    state : u64,
+}
+
+impl Random {
+    pub fn new(seed : u64) -> Random {
+        Random { state: seed }
+    }
+}
+
+impl RngCore for Random {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        finalize(self.state)
+    }
+
+    fn fill_bytes(&mut self, dest : &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest : &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+fn finalize(mut z : u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+//Mix several values in a seed, so close inputs give unrelated seeds
+pub fn mix(values : &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |state, &value| finalize((state ^ value).wrapping_add(0x9e3779b97f4a7c15)))
+}
@@ -2,14 +2,16 @@
 
 use std::sync::Arc;
 
-use crate::material::{Texture, Material, dielectric};
+use crate::material::{Texture, Material};
 use crate::point::Point;
 use crate::color::Color;
-use crate::world::{World, Hitable, Surface};
+use crate::world::{World, Surface, Hit};
+use crate::sampler::Sampler;
 pub struct Ray{
     orig : Point<f64>,
     dir : Point<f64>,
     depth : u32,
+    time : f64, //Since the opening of the shutter, in seconds
 }
 
 impl Ray {
@@ -18,9 +20,19 @@ impl Ray {
             orig,
             dir,
             depth,
+            time : 0.,
         }
     }
 
+    pub fn at_time(mut self, time : f64) -> Ray {
+        self.time = time;
+        self
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
     pub fn get_depth(&self) -> u32 {
         self.depth
     }
@@ -38,84 +50,154 @@ impl Ray {
         self.orig + self.dir*t
     }
 
-    pub fn object_hitted<'a, 'b>(&'a self, world : &'b World) -> Option<(f64, &'b Surface, Arc<Texture>)> {
-        let mut best_t : Option<(f64, &Surface, Arc<Texture>)> = None;
+    pub fn object_hitted<'b>(&self, world : &'b World) -> Option<(Hit, &'b Surface, Arc<Texture>)> {
+        let mut best_t : Option<(Hit, &Surface, Arc<Texture>)> = None;
 
-        let t_max = f64::MAX;
         let t_min = 0.001;
         
         for (surface, texture) in &world.objects {
-            match &surface {
-                &Surface::Sphere(sphere) => {
-                    if let Some(t) = sphere.hit(&self, t_min, t_max) {
-                        best_t = match best_t {
-                            None => Some((t, surface, Arc::clone(texture))),
-                            Some((best_t, best_surf, best_text)) => {
-                                if best_t>t {
-                                    Some((t, surface, Arc::clone(texture)))
-                                }
-                                else{
-                                    Some((best_t, best_surf, best_text))
-                                }
-                            }
-                        };
-                    }
-                }
-                &Surface::AABB(aabb) => {
-                    if let Some((res_t, res_surf, res_texture)) = aabb.hit(&self, t_min, t_max) {
-                        best_t = match best_t {
-                            None => Some((res_t, res_surf, res_texture)),
-                            Some((best_t, best_surf, best_text)) => {
-                                if best_t > res_t {
-                                    Some((res_t, res_surf, res_texture))
-                                }
-                                else {
-                                    Some((best_t, best_surf, best_text))
-                                }
-                            }
-                        }
-                    }
+            //Only look for hits closer than the best one found yet
+            let t_max = best_t.as_ref().map_or(f64::MAX, |(hit, _, _)| hit.t);
+            let hit = match surface {
+                Surface::AABB(aabb) => aabb.hit(self, t_min, t_max),
+                _ => surface.as_hitable().and_then(|hitable| hitable.hit(self, t_min, t_max)).map(|t| (t, surface, Arc::clone(texture))),
+            };
+
+            if let Some((hit, surf, text)) = hit {
+                if hit.t < t_max {
+                    best_t = Some((hit, surf, text));
                 }
             }
         }
 
-        return best_t;
+        best_t
     }
 
-    pub fn color(self, world : &World) -> Color<f64> {
+    pub fn color(self, world : &World, sampler : &mut Sampler) -> Color<f64> {
+        self.trace(world, sampler).0
+    }
+
+    //Color and alpha of the ray, the alpha is 0 where a camera ray leaves to a background the camera doesn't see
+    pub fn trace(self, world : &World, sampler : &mut Sampler) -> (Color<f64>, f64) {
+        self.trace_recorded(world, sampler, None)
+    }
+
+    //Same as trace(), the camera ray and its first bounce fill the record on the way
+    pub fn trace_recorded<'a>(self, world : &'a World, sampler : &mut Sampler, mut record : Option<&mut PathRecord<'a>>) -> (Color<f64>, f64) {
         if self.depth > 50 {
-            return Color {r: 0., g: 0., b: 0.};
+            return (Color {r: 0., g: 0., b: 0.}, 1.);
         }
         
         match self.object_hitted(world) {
+            //Nothing is recorded either, the hidden background has no albedo
+            None if self.depth == 0 && !world.background_visible => (Color { r: 0., g: 0., b: 0. }, 0.),
             None => {
-                let unit_dir = self.dir.unit();
-                let a : f64 = (unit_dir.y+1.0)*0.5;
-                Color {r: 1.0, g: 1.0, b: 1.0}*(1.0-a) + a*Color { r: 0.5, g: 0.7, b: 1.0 }
-            }
-            Some((t, surface, texture)) => {
-                let best_record = match surface {
-                    Surface::Sphere(sphere) => sphere.get_records(&self, t),
-                    _ => panic!("Shouldn't have an aabb as a result of object_hitted()")
-                };
+                let color = world.background.color(&self.dir);
+                if let Some(record) = record {
+                    if self.depth == 0 {
+                        record.albedo = Color { r: color.r.min(1.), g: color.g.min(1.), b: color.b.min(1.) };
+                        record.direct = color;
+                    } else {
+                        record.bounce_is_direct = true;
+                    }
+                }
+                (color, 1.)
+            },
+            Some((hit, surface, texture)) => {
+                let best_record = surface.as_hitable()
+                    .expect("Shouldn't have an aabb as a result of object_hitted()")
+                    .get_records(&self, &hit);
+                let depth = self.depth;
+                if let Some(record) = record.as_deref_mut() {
+                    if depth == 0 {
+                        record.albedo = texture.albedo(&best_record);
+                        record.first = Some(FirstHit {
+                            p : best_record.p,
+                            normal : best_record.normal,
+                            depth : (best_record.p - self.orig).norm(),
+                            surface,
+                            texture : Arc::clone(&texture),
+                        });
+                    } else {
+                        record.bounce_is_direct = matches!(texture.as_ref(), Texture::Emissive(_));
+                    }
+                }
+                sampler.start_vertex(depth);
+                let time = self.time;
 
                 let (ray, color) = match texture.as_ref() {
-                    Texture::Diffuse(diffuse) => diffuse.scatter(self, best_record),
-                    Texture::Metal(metal) => metal.scatter(self, best_record),
-                    Texture::Dielectric(dielectric) => dielectric.scatter(self, best_record),
+                    Texture::Diffuse(diffuse) => diffuse.scatter(self, best_record, sampler),
+                    Texture::Metal(metal) => metal.scatter(self, best_record, sampler),
+                    Texture::Dielectric(dielectric) => dielectric.scatter(self, best_record, sampler),
+                    Texture::Pbr(pbr) => pbr.scatter(self, best_record, sampler),
+                    Texture::Emissive(light) => {
+                        let emitted = light.emitted(&best_record);
+                        if let Some(record) = record.filter(|_| depth == 0) {
+                            record.direct = emitted;
+                        }
+                        return (emitted, 1.);
+                    },
                 };
-                ray.color(world)*color
+                //Only the first bounce tells the camera ray how its light splits
+                let (bounce, _) = ray.at_time(time).trace_recorded(world, sampler, record.as_deref_mut().filter(|_| depth == 0));
+                let bounce = bounce*color;
+                if let Some(record) = record.filter(|_| depth == 0) {
+                    if record.bounce_is_direct {
+                        record.direct = bounce;
+                    } else {
+                        record.indirect = bounce;
+                    }
+                }
+                (bounce, 1.)
             }
         }
     }
 
 }
 
+//What the path of a camera ray goes through, for the auxiliary buffers of the image
+pub struct PathRecord<'a> {
+    pub first : Option<FirstHit<'a>>, //None where the ray leaves to the background
+    pub albedo : Color<f64>, //Of the first hit, or the background clamped to 1 if the camera sees it
+    pub direct : Color<f64>, //Lights and background seen directly or after one bounce
+    pub indirect : Color<f64>, //The rest of the color of the path, so direct + indirect is its color
+    bounce_is_direct : bool, //Set by the first bounce, whether it ends on a light or the background
+}
+
+impl PathRecord<'_> {
+    //Nothing seen, as for a ray the camera doesn't cast
+    pub fn new() -> Self {
+        PathRecord {
+            first : None,
+            albedo : Color { r: 0., g: 0., b: 0. },
+            direct : Color { r: 0., g: 0., b: 0. },
+            indirect : Color { r: 0., g: 0., b: 0. },
+            bounce_is_direct : false,
+        }
+    }
+}
+
+impl Default for PathRecord<'_> {
+    fn default() -> Self {
+        PathRecord::new()
+    }
+}
+
+//The first surface a camera ray hits
+pub struct FirstHit<'a> {
+    pub p : Point<f64>,
+    pub normal : Point<f64>, //Shading normal
+    pub depth : f64, //Distance to the origin of the ray
+    pub surface : &'a Surface,
+    pub texture : Arc<Texture>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[allow(unused_variables)]
     fn operation(){
         let a = Ray::new(Point { x: 3.0, y: 2.0, z: 1.0 }, Point { x: 1.0, y: 1.0, z: 0.0 }, 0);
         let b = Ray::new(Point { x: 5.0, y: 0.5, z: 0.3 }, Point { x: 2.0, y: 1.0, z: 3.0 }, 0);
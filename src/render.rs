@@ -0,0 +1,862 @@
+pub mod aov;
+pub mod checkpoint;
+pub mod denoise;
+pub mod distributed;
+pub mod exr;
+pub mod filter;
+
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path, thread, time::{Duration, Instant}};
+use std::sync::{Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+use crate::{Camera, camera::stereo::{Stereo, Eye}, world::World, color::{Color, display::Display}, sampler::{Sampler, SamplerKind}, ray::PathRecord};
+use filter::Filter;
+use aov::{Aovs, AovSum};
+
+//Side of the square tiles the image is cut into, in pixels
+pub const TILE_SIZE : u32 = 32;
+//Darker pixels compare their error to this luminance, so the noise in the black isn't chased forever
+const MIN_LUMINANCE : f64 = 0.05;
+
+//The accumulated samples of the image
+pub struct Framebuffer {
+    width : u32,
+    height : u32,
+    sums : Vec<Color<f64>>, //Sum of the samples splatted on each pixel weighted by the filter, row by row
+    weights : Vec<f64>, //Sum of the weights of these samples
+    alphas : Vec<f64>, //Sum of their alphas with the same weights, so the colors are premultiplied
+    squares : Vec<f64>, //Sum of the squared luminances of the samples, for the variance
+    samples : Vec<u32>, //Number of samples taken in each pixel
+}
+
+impl Framebuffer {
+    pub fn new(width : u32, height : u32) -> Framebuffer {
+        let size = (width*height) as usize;
+        Framebuffer {
+            width,
+            height,
+            sums : vec![Color { r: 0., g: 0., b: 0. }; size],
+            weights : vec![0.; size],
+            alphas : vec![0.; size],
+            squares : vec![0.; size],
+            samples : vec![0; size],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn samples(&self, i : u32, j : u32) -> u32 {
+        self.samples[(j*self.width + i) as usize]
+    }
+
+    //Weighted average of the samples splatted on the pixel, black if it has none
+    pub fn color(&self, i : u32, j : u32) -> Color<f64> {
+        self.mean((j*self.width + i) as usize)
+    }
+
+    fn mean(&self, index : usize) -> Color<f64> {
+        //The negative lobes of some filters can cancel the weights
+        if self.weights[index].abs() < 1e-12 {
+            return Color { r: 0., g: 0., b: 0. };
+        }
+        self.sums[index]*(1./self.weights[index])
+    }
+
+    //Part of the pixel covered by what the camera sees, 0 for the transparent background or without samples
+    pub fn alpha(&self, i : u32, j : u32) -> f64 {
+        let index = (j*self.width + i) as usize;
+        if self.weights[index].abs() < 1e-12 {
+            return 0.;
+        }
+        self.alphas[index]/self.weights[index]
+    }
+
+    //Estimated relative error of the mean luminance of the pixel : its standard error over the mean
+    //Infinite until the pixel has two samples
+    //The variance of the samples of the pixel is taken around its filtered color
+    pub fn error(&self, i : u32, j : u32) -> f64 {
+        let index = (j*self.width + i) as usize;
+        let n = self.samples[index] as f64;
+        if n < 2. {
+            return f64::INFINITY;
+        }
+        let mean = self.mean(index).luminance();
+        let variance = ((self.squares[index]/n - mean*mean)*n/(n - 1.)).max(0.);
+        (variance/n).sqrt()/mean.max(MIN_LUMINANCE)
+    }
+
+    //Add the samples of a tile rendered apart, with the number of samples added to each of its pixels
+    fn add_tile(&mut self, tile : &Tile, sums : &[Color<f64>], weights : &[f64], alphas : &[f64], squares : &[f64], samples : &[u32]) {
+        for j in 0..tile.height {
+            for i in 0..tile.width {
+                let index = ((tile.y + j)*self.width + tile.x + i) as usize;
+                let tile_index = (j*tile.width + i) as usize;
+                self.sums[index] = self.sums[index] + sums[tile_index];
+                self.weights[index] += weights[tile_index];
+                self.alphas[index] += alphas[tile_index];
+                self.squares[index] += squares[tile_index];
+                self.samples[index] += samples[tile_index];
+            }
+        }
+    }
+
+    //Fewest samples of a pixel
+    pub fn min_samples(&self) -> u32 {
+        self.samples.iter().copied().min().unwrap_or(0)
+    }
+
+    //Write the normalized image in the ppm format
+    pub fn write_ppm<W : Write>(&self, mut out : W, display : &Display) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+        writeln!(out, "255")?;
+        for index in 0..self.sums.len() {
+            self.mean(index).write(&mut out, display, index as u32%self.width, index as u32/self.width)?;
+        }
+        out.flush()
+    }
+
+    pub fn save_ppm<P : AsRef<Path>>(&self, path : P, display : &Display) -> io::Result<()> {
+        self.write_ppm(BufWriter::new(File::create(path)?), display)
+    }
+
+    //Write the image with its alpha in the png format
+    //The png alpha is straight, the colors are divided by it before the display transform
+    pub fn write_png<W : Write>(&self, out : W, display : &Display) -> io::Result<()> {
+        let mut data = Vec::with_capacity(self.sums.len()*4);
+        for index in 0..self.sums.len() {
+            let (i, j) = (index as u32%self.width, index as u32/self.width);
+            let alpha = self.alpha(i, j).clamp(0., 1.);
+            if alpha <= 0. {
+                data.extend_from_slice(&[0; 4]);
+                continue;
+            }
+            data.extend_from_slice(&display.to_srgb8(self.mean(index)*(1./alpha), i, j));
+            data.push((alpha*255.).round() as u8);
+        }
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn save_png<P : AsRef<Path>>(&self, path : P, display : &Display) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?), display)
+    }
+
+    //Write the linear colors, premultiplied by the alpha, and the alpha in the OpenEXR format
+    pub fn write_exr<W : Write>(&self, out : W) -> io::Result<()> {
+        let size = self.sums.len();
+        let (mut r, mut g, mut b, mut a) = (Vec::with_capacity(size), Vec::with_capacity(size), Vec::with_capacity(size), Vec::with_capacity(size));
+        for index in 0..size {
+            let color = self.mean(index);
+            r.push(color.r as f32);
+            g.push(color.g as f32);
+            b.push(color.b as f32);
+            a.push(self.alpha(index as u32%self.width, index as u32/self.width) as f32);
+        }
+        exr::write(out, self.width, self.height, &[("R", &r), ("G", &g), ("B", &b), ("A", &a)])
+    }
+
+    pub fn save_exr<P : AsRef<Path>>(&self, path : P) -> io::Result<()> {
+        self.write_exr(BufWriter::new(File::create(path)?))
+    }
+
+    //Write the number of samples of each pixel in the ppm format, from blue for none to green then red for the most
+    pub fn write_heatmap<W : Write>(&self, mut out : W) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+        writeln!(out, "255")?;
+        let max = self.samples.iter().copied().max().unwrap_or(0).max(1) as f64;
+        for &samples in &self.samples {
+            let t = samples as f64/max;
+            let (r, g, b) = if t < 0.5 { (0., 2.*t, 1. - 2.*t) } else { (2.*t - 1., 2. - 2.*t, 0.) };
+            writeln!(out, "{} {} {}", (r*255.) as u8, (g*255.) as u8, (b*255.) as u8)?;
+        }
+        out.flush()
+    }
+
+    pub fn save_heatmap<P : AsRef<Path>>(&self, path : P) -> io::Result<()> {
+        self.write_heatmap(BufWriter::new(File::create(path)?))
+    }
+
+    //The pixels of the window alone, as an image of its size
+    pub fn crop(&self, window : &CropWindow) -> Framebuffer {
+        let region = window.region(self.width, self.height);
+        let mut cropped = Framebuffer::new(region.width, region.height);
+        for j in 0..region.height {
+            let from = ((region.y + j)*self.width + region.x) as usize..((region.y + j)*self.width + region.x + region.width) as usize;
+            let to = (j*region.width) as usize..((j + 1)*region.width) as usize;
+            cropped.sums[to.clone()].copy_from_slice(&self.sums[from.clone()]);
+            cropped.weights[to.clone()].copy_from_slice(&self.weights[from.clone()]);
+            cropped.alphas[to.clone()].copy_from_slice(&self.alphas[from.clone()]);
+            cropped.squares[to.clone()].copy_from_slice(&self.squares[from.clone()]);
+            cropped.samples[to].copy_from_slice(&self.samples[from]);
+        }
+        cropped
+    }
+
+    //Both images of a stereo pair in one, the left eye on the left or on the top
+    pub fn pack(left : &Framebuffer, right : &Framebuffer, layout : Layout) -> Framebuffer {
+        assert!(left.width == right.width && left.height == right.height, "The two eyes have the same size");
+        let (width, height) = match layout {
+            Layout::SideBySide => (left.width*2, left.height),
+            Layout::TopBottom => (left.width, left.height*2),
+        };
+        let mut packed = Framebuffer::new(width, height);
+        for (eye, framebuffer) in [left, right].into_iter().enumerate() {
+            let (x, y) = match layout {
+                Layout::SideBySide => (eye as u32*left.width, 0),
+                Layout::TopBottom => (0, eye as u32*left.height),
+            };
+            for j in 0..framebuffer.height {
+                let from = (j*framebuffer.width) as usize..((j + 1)*framebuffer.width) as usize;
+                let to = ((j + y)*width + x) as usize;
+                let to = to..to + framebuffer.width as usize;
+                packed.sums[to.clone()].copy_from_slice(&framebuffer.sums[from.clone()]);
+                packed.weights[to.clone()].copy_from_slice(&framebuffer.weights[from.clone()]);
+                packed.alphas[to.clone()].copy_from_slice(&framebuffer.alphas[from.clone()]);
+                packed.squares[to.clone()].copy_from_slice(&framebuffer.squares[from.clone()]);
+                packed.samples[to].copy_from_slice(&framebuffer.samples[from]);
+            }
+        }
+        packed
+    }
+}
+
+//How the images of the two eyes are put together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    SideBySide,
+    TopBottom,
+}
+
+//Rectangle of the image rendered alone, in pixels, its rays are the ones of the whole image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CropWindow {
+    pub x : u32,
+    pub y : u32,
+    pub width : u32,
+    pub height : u32,
+}
+
+impl CropWindow {
+    pub fn new(x : u32, y : u32, width : u32, height : u32) -> CropWindow {
+        CropWindow { x, y, width, height }
+    }
+
+    //The part of the window inside an image of this size
+    fn region(&self, width : u32, height : u32) -> Tile {
+        let (x, y) = (self.x.min(width), self.y.min(height));
+        Tile { x, y, width: self.width.min(width - x), height: self.height.min(height - y) }
+    }
+}
+
+//A rectangle of pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tile {
+    x : u32,
+    y : u32,
+    width : u32,
+    height : u32,
+}
+
+impl Tile {
+    fn image(camera : &Camera) -> Tile {
+        Tile { x: 0, y: 0, width: camera.image_width, height: camera.image_height }
+    }
+
+    //The rectangle grown by margin pixels on each side, inside the image
+    fn expand(&self, margin : u32, width : u32, height : u32) -> Tile {
+        let (x, y) = (self.x.saturating_sub(margin), self.y.saturating_sub(margin));
+        Tile { x, y, width: (self.x + self.width + margin).min(width) - x, height: (self.y + self.height + margin).min(height) - y }
+    }
+
+    //Cut the rectangle in tiles, row by row, the last ones of each row and column may be smaller
+    fn tiles(&self) -> Vec<Tile> {
+        let mut tiles = vec![];
+        for y in (self.y..self.y + self.height).step_by(TILE_SIZE as usize) {
+            for x in (self.x..self.x + self.width).step_by(TILE_SIZE as usize) {
+                tiles.push(Tile { x, y, width: TILE_SIZE.min(self.x + self.width - x), height: TILE_SIZE.min(self.y + self.height - y) });
+            }
+        }
+        tiles
+    }
+}
+
+//A pass adding samples to every pixel of a region of the framebuffer
+struct Pass<'a> {
+    camera : &'a Camera,
+    world : &'a World,
+    threads : usize,
+    region : Tile,
+    samples : u32, //Samples added to each pixel
+    target : u32, //No pixel goes over this number of samples
+    first_sample : u32, //Index of the first sample of a pixel that has none yet
+    sampler : SamplerKind,
+    filter : Filter,
+    seed : u64, //The numbers of each sample only depend on it, the pixel and the index of the sample
+    mask : Option<&'a [bool]>, //Only the pixels set to true get samples, all of them if None
+    aovs : Option<&'a Mutex<Aovs>>, //Where the samples record the auxiliary buffers, if they do
+    log_tiles : bool,
+}
+
+impl Pass<'_> {
+    //The threads take the next tile of the queue as soon as they are done with one, so none waits for the others
+    //Once stop() returns true no new tile is started, the pixels of the remaining ones keep their previous samples
+    //The samples are splatted on the pixels around them, so a tile is added with a margin of the radius of the filter
+    fn run(&self, framebuffer : &Mutex<Framebuffer>, stop : &(dyn Fn() -> bool + Sync)) {
+        let camera = self.camera;
+        let margin = self.filter.margin();
+        let mut tiles = self.region.tiles();
+        if let Some(mask) = self.mask {
+            tiles.retain(|tile| (tile.y..tile.y + tile.height).any(|j| (tile.x..tile.x + tile.width).any(|i| mask[(j*camera.image_width + i) as usize])));
+        }
+        let next = AtomicUsize::new(0);
+        let before = framebuffer.lock().expect("A render thread panicked").samples.clone();
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                scope.spawn(|| {
+                    let mut sums = vec![];
+                    let mut weights = vec![];
+                    let mut alphas = vec![];
+                    let mut squares = vec![];
+                    let mut samples = vec![];
+                    let mut aov_sums = vec![];
+                    loop {
+                        if stop() {
+                            break;
+                        }
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(tile) = tiles.get(index) else {
+                            break;
+                        };
+
+                        let area = tile.expand(margin, camera.image_width, camera.image_height);
+                        let size = (area.width*area.height) as usize;
+                        sums.clear();
+                        sums.resize(size, Color { r: 0., g: 0., b: 0. });
+                        weights.clear();
+                        weights.resize(size, 0.);
+                        alphas.clear();
+                        alphas.resize(size, 0.);
+                        squares.clear();
+                        squares.resize(size, 0.);
+                        samples.clear();
+                        samples.resize(size, 0);
+                        aov_sums.clear();
+                        if self.aovs.is_some() {
+                            aov_sums.resize((tile.width*tile.height) as usize, AovSum::new());
+                        }
+                        for j in tile.y..tile.y + tile.height {
+                            for i in tile.x..tile.x + tile.width {
+                                let pixel = (j*camera.image_width + i) as usize;
+                                let done = before[pixel];
+                                let count = match self.mask {
+                                    Some(mask) if !mask[pixel] => 0,
+                                    _ => self.samples.min(self.target.saturating_sub(done)),
+                                };
+                                let own = ((j - area.y)*area.width + i - area.x) as usize;
+                                for sample in self.first_sample + done..self.first_sample + done + count {
+                                    let mut sampler = Sampler::new(self.sampler, self.seed, i, j, sample, self.first_sample + self.target);
+                                    //Antialiasing random on the position in the pixel
+                                    let (dx, dy) = sampler.get_2d();
+                                    let (dx, dy) = (dx - 0.5, dy - 0.5);
+                                    let mut record = self.aovs.map(|_| PathRecord::new());
+                                    let (color, alpha) = match camera.film_ray(i as f64 + dx, j as f64 + dy, &mut sampler) {
+                                        Some(ray) => {
+                                            let (color, alpha) = ray.trace_recorded(self.world, &mut sampler, record.as_mut());
+                                            (color*camera.exposure.scale(), alpha)
+                                        },
+                                        None => (Color { r: 0., g: 0., b: 0. }, 1.),
+                                    };
+                                    if let Some(record) = &record {
+                                        aov_sums[((j - tile.y)*tile.width + i - tile.x) as usize].add(record, camera.exposure.scale());
+                                    }
+                                    squares[own] += color.luminance()*color.luminance();
+
+                                    for y in j.saturating_sub(margin)..(j + margin + 1).min(camera.image_height) {
+                                        for x in i.saturating_sub(margin)..(i + margin + 1).min(camera.image_width) {
+                                            let weight = self.filter.weight(x as f64 - i as f64 - dx, y as f64 - j as f64 - dy);
+                                            if weight != 0. {
+                                                let index = ((y - area.y)*area.width + x - area.x) as usize;
+                                                sums[index] = sums[index] + color*weight;
+                                                weights[index] += weight;
+                                                alphas[index] += alpha*weight;
+                                            }
+                                        }
+                                    }
+                                }
+                                samples[own] = count;
+                            }
+                        }
+
+                        framebuffer.lock().expect("A render thread panicked").add_tile(&area, &sums, &weights, &alphas, &squares, &samples);
+                        if let Some(aovs) = self.aovs {
+                            aovs.lock().expect("A render thread panicked").add_tile(tile, &aov_sums);
+                        }
+                        if self.log_tiles {
+                            eprintln!("Rendered tile {}/{}", index + 1, tiles.len());
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
+
+//Render the image with exactly samples_per_pixel samples in every pixel
+//The same seed gives the same image, whatever the number of threads
+//(up to the rounding of the sums for the filters wider than a pixel, the tiles are added in any order)
+pub fn render(camera : &Camera, world : &World, samples_per_pixel : u32, threads : usize, sampler : SamplerKind, filter : Filter, seed : u64) -> Framebuffer {
+    let framebuffer = Mutex::new(Framebuffer::new(camera.image_width, camera.image_height));
+    let pass = Pass {
+        camera,
+        world,
+        threads,
+        region : Tile::image(camera),
+        samples : samples_per_pixel,
+        target : samples_per_pixel,
+        first_sample : 0,
+        sampler,
+        filter,
+        seed,
+        mask : None,
+        aovs : None,
+        log_tiles : true,
+    };
+    pass.run(&framebuffer, &|| false);
+    framebuffer.into_inner().expect("A render thread panicked")
+}
+
+//Render the image as render() does, its samples record the auxiliary buffers on the way
+pub fn render_aovs(camera : &Camera, world : &World, samples_per_pixel : u32, threads : usize, sampler : SamplerKind, filter : Filter, seed : u64) -> (Framebuffer, Aovs) {
+    let framebuffer = Mutex::new(Framebuffer::new(camera.image_width, camera.image_height));
+    let aovs = Mutex::new(Aovs::new(world, camera.image_width, camera.image_height));
+    let pass = Pass {
+        camera,
+        world,
+        threads,
+        region : Tile::image(camera),
+        samples : samples_per_pixel,
+        target : samples_per_pixel,
+        first_sample : 0,
+        sampler,
+        filter,
+        seed,
+        mask : None,
+        aovs : Some(&aovs),
+        log_tiles : true,
+    };
+    pass.run(&framebuffer, &|| false);
+    (framebuffer.into_inner().expect("A render thread panicked"), aovs.into_inner().expect("A render thread panicked"))
+}
+
+//Render only the pixels of the window, the others have no samples and stay black
+//The pixels around it within the radius of the filter are sampled too, so the ones of its edges are the same
+//as in the render of the whole image
+#[allow(clippy::too_many_arguments)]
+pub fn render_crop(camera : &Camera, world : &World, window : &CropWindow, samples_per_pixel : u32, threads : usize, sampler : SamplerKind, filter : Filter, seed : u64) -> Framebuffer {
+    let region = window.region(camera.image_width, camera.image_height);
+    let framebuffer = Mutex::new(Framebuffer::new(camera.image_width, camera.image_height));
+    let pass = Pass {
+        camera,
+        world,
+        threads,
+        region : region.expand(filter.margin(), camera.image_width, camera.image_height),
+        samples : samples_per_pixel,
+        target : samples_per_pixel,
+        first_sample : 0,
+        sampler,
+        filter,
+        seed,
+        mask : None,
+        aovs : None,
+        log_tiles : true,
+    };
+    pass.run(&framebuffer, &|| false);
+    let mut framebuffer = framebuffer.into_inner().expect("A render thread panicked");
+
+    for j in 0..camera.image_height {
+        for i in 0..camera.image_width {
+            if i < region.x || i >= region.x + region.width || j < region.y || j >= region.y + region.height {
+                let index = (j*camera.image_width + i) as usize;
+                framebuffer.sums[index] = Color { r: 0., g: 0., b: 0. };
+                framebuffer.weights[index] = 0.;
+                framebuffer.alphas[index] = 0.;
+                framebuffer.squares[index] = 0.;
+                framebuffer.samples[index] = 0;
+            }
+        }
+    }
+    framebuffer
+}
+
+//Render the left and the right eye of the rig around the camera
+//They share the seed, so their noise matches and doesn't show as a difference between the eyes
+#[allow(clippy::too_many_arguments)]
+pub fn render_stereo(camera : &Camera, world : &World, stereo : &Stereo, samples_per_pixel : u32, threads : usize, sampler : SamplerKind, filter : Filter, seed : u64) -> (Framebuffer, Framebuffer) {
+    let left = render(&stereo.eye(camera, Eye::Left), world, samples_per_pixel, threads, sampler, filter, seed);
+    let right = render(&stereo.eye(camera, Eye::Right), world, samples_per_pixel, threads, sampler, filter, seed);
+    (left, right)
+}
+
+//Settings of a progressive render
+pub struct Progressive {
+    pub target_samples : u32, //Samples per pixel at the end of the render
+    pub samples_per_pass : u32,
+    pub time_budget : Option<Duration>, //The render stops at the first tile started after it
+    pub snapshot_interval : Option<Duration>, //Minimum time between two snapshots, None for one after each pass
+    pub sampler : SamplerKind,
+    pub filter : Filter,
+    pub seed : u64,
+}
+
+impl Progressive {
+    pub fn new(target_samples : u32, samples_per_pass : u32) -> Progressive {
+        Progressive {
+            target_samples,
+            samples_per_pass : samples_per_pass.max(1),
+            time_budget : None,
+            snapshot_interval : None,
+            sampler : SamplerKind::Sobol,
+            filter : Filter::Box(0.5),
+            seed : 0,
+        }
+    }
+}
+
+//Render the whole image again and again until the target, the time budget or the cancellation is reached
+//snapshot() receives the framebuffer between the passes and once more at the end if the last state wasn't given yet
+//A pass interrupted by the budget or the cancellation leaves some pixels with fewer samples than the others
+pub fn render_progressive<F : FnMut(&Framebuffer)>(camera : &Camera, world : &World, threads : usize, settings : &Progressive, cancel : &AtomicBool, snapshot : F) -> Framebuffer {
+    let framebuffer = Framebuffer::new(camera.image_width, camera.image_height);
+    continue_progressive(camera, world, threads, settings, cancel, framebuffer, snapshot)
+}
+
+//Same as render_progressive() but adding the samples to an existing framebuffer, until each pixel has the target
+fn continue_progressive<F : FnMut(&Framebuffer)>(camera : &Camera, world : &World, threads : usize, settings : &Progressive, cancel : &AtomicBool, framebuffer : Framebuffer, mut snapshot : F) -> Framebuffer {
+    let start = Instant::now();
+    let deadline = settings.time_budget.map(|budget| start + budget);
+    let stop = || cancel.load(Ordering::Relaxed) || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+    let pass = Pass {
+        camera,
+        world,
+        threads,
+        region : Tile::image(camera),
+        samples : settings.samples_per_pass,
+        target : settings.target_samples,
+        first_sample : 0,
+        sampler : settings.sampler,
+        filter : settings.filter,
+        seed : settings.seed,
+        mask : None,
+        aovs : None,
+        log_tiles : false,
+    };
+    let mut done = framebuffer.min_samples();
+    let framebuffer = Mutex::new(framebuffer);
+    let mut last_snapshot = start;
+    let mut up_to_date = true;
+
+    while done < settings.target_samples && !stop() {
+        pass.run(&framebuffer, &stop);
+        let framebuffer = framebuffer.lock().expect("A render thread panicked");
+        done = framebuffer.min_samples();
+        up_to_date = false;
+        eprintln!("Pass done, {done}/{} samples per pixel", settings.target_samples);
+
+        if settings.snapshot_interval.is_none_or(|interval| last_snapshot.elapsed() >= interval) {
+            snapshot(&framebuffer);
+            last_snapshot = Instant::now();
+            up_to_date = true;
+        }
+    }
+
+    let framebuffer = framebuffer.into_inner().expect("A render thread panicked");
+    if !up_to_date {
+        snapshot(&framebuffer);
+    }
+    framebuffer
+}
+
+//Settings of an adaptive render
+pub struct Adaptive {
+    pub min_samples : u32, //Samples of every pixel before its error is trusted
+    pub max_samples : u32,
+    pub samples_per_pass : u32, //Samples added to the pixels not converged at each pass
+    pub threshold : f64, //Relative error under which a pixel is converged
+    pub sampler : SamplerKind,
+    pub filter : Filter,
+    pub seed : u64,
+}
+
+impl Adaptive {
+    pub fn new(threshold : f64, max_samples : u32) -> Adaptive {
+        Adaptive {
+            min_samples : max_samples.min(16),
+            max_samples,
+            samples_per_pass : 8,
+            threshold,
+            sampler : SamplerKind::Sobol,
+            filter : Filter::Box(0.5),
+            seed : 0,
+        }
+    }
+}
+
+//Render min_samples in every pixel then add samples where the error is over the threshold,
+//until every pixel is converged or has max_samples
+//A pixel stays active while one of its neighbours isn't converged, so a small feature
+//missed by the first samples of a pixel still gets found from the next ones
+pub fn render_adaptive(camera : &Camera, world : &World, threads : usize, settings : &Adaptive) -> Framebuffer {
+    let (width, height) = (camera.image_width, camera.image_height);
+    let framebuffer = Mutex::new(Framebuffer::new(width, height));
+    let first = Pass {
+        camera,
+        world,
+        threads,
+        region : Tile::image(camera),
+        samples : settings.min_samples.max(2),
+        target : settings.max_samples,
+        first_sample : 0,
+        sampler : settings.sampler,
+        filter : settings.filter,
+        seed : settings.seed,
+        mask : None,
+        aovs : None,
+        log_tiles : false,
+    };
+    first.run(&framebuffer, &|| false);
+
+    loop {
+        let active = {
+            let framebuffer = framebuffer.lock().expect("A render thread panicked");
+            let noisy : Vec<bool> = (0..height).flat_map(|j| (0..width).map(move |i| (i, j)))
+                .map(|(i, j)| framebuffer.error(i, j) > settings.threshold)
+                .collect();
+            let mut active = vec![false; noisy.len()];
+            for j in 0..height {
+                for i in 0..width {
+                    let index = (j*width + i) as usize;
+                    let neighbours = (j.saturating_sub(1)..(j + 2).min(height)).any(|y| (i.saturating_sub(1)..(i + 2).min(width)).any(|x| noisy[(y*width + x) as usize]));
+                    active[index] = neighbours && framebuffer.samples[index] < settings.max_samples;
+                }
+            }
+            active
+        };
+        let remaining = active.iter().filter(|&&active| active).count();
+        eprintln!("Adaptive pass, {remaining} pixels not converged");
+        if remaining == 0 {
+            break;
+        }
+        let pass = Pass { samples : settings.samples_per_pass.max(1), mask : Some(&active), ..first };
+        pass.run(&framebuffer, &|| false);
+    }
+    framebuffer.into_inner().expect("A render thread panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{Exposure, point::Point, transform::Transform, world::{Background, Surface, sphere::Sphere}, material::{Texture, diffuse::Diffuse}};
+
+    #[test]
+    fn exact_samples_on_every_pixel() {
+        //The size isn't a multiple of the tiles
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 70, 70./45., 60., 0., 1.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.25, g: 0.5, b: 1. });
+
+        let framebuffer = render(&camera, &world, 7, 3, SamplerKind::Independent, Filter::Box(0.5), 0);
+        assert_eq!((framebuffer.width(), framebuffer.height()), (70, 45));
+        for j in 0..45 {
+            for i in 0..70 {
+                assert_eq!(framebuffer.samples(i, j), 7);
+                assert!((framebuffer.color(i, j).g - 0.5).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn progressive_passes_and_cancel() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 2., 60., 0., 1.);
+        let world = World::new();
+        let not_cancelled = AtomicBool::new(false);
+
+        let mut snapshots = vec![];
+        let framebuffer = render_progressive(&camera, &world, 2, &Progressive::new(5, 2), &not_cancelled, |framebuffer| snapshots.push(framebuffer.samples(0, 0)));
+        assert_eq!(snapshots, vec![2, 4, 5]);
+        assert_eq!(framebuffer.samples(39, 19), 5);
+
+        //Cancelled from the first snapshot
+        let cancel = AtomicBool::new(false);
+        let mut count = 0;
+        let framebuffer = render_progressive(&camera, &world, 2, &Progressive::new(5, 2), &cancel, |_| {
+            count += 1;
+            cancel.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(count, 1);
+        assert_eq!(framebuffer.samples(20, 10), 2);
+    }
+
+    #[test]
+    fn exposure_and_motion_blur() {
+        let mut camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 1., 60., 0., 3.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.25, g: 0.25, b: 0.25 });
+        let black = Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0., g: 0., b: 0. })));
+        //Crosses the center of the image while the shutter is open
+        let sphere = Arc::new(Surface::Sphere(Sphere::new(Point { x: 0., y: 0., z: 0. }, 0.2)));
+        world.add_moving_instance(sphere, Transform::translate(Point { x: -0.4, y: 0., z: -3. }), Point { x: 80., y: 0., z: 0. }, camera.exposure.shutter, black);
+
+        //Sunny 16 gives the light as it is, f/8 lets 4 times more in
+        assert!((Exposure::default().scale() - 1.).abs() < 1e-12 && (Exposure::default().ev100() - 14.64).abs() < 0.01);
+        assert!((Exposure::new(100., 1./100., 8.).scale() - 4.).abs() < 1e-12);
+
+        let image = render(&camera, &world, 64, 1, SamplerKind::Sobol, Filter::Box(0.5), 0);
+        assert!((image.color(0, 0).g - 0.25).abs() < 1e-12);
+        //The sphere only covers the center for a part of the exposure
+        let center = image.color(20, 20).g;
+        assert!(center > 0.05 && center < 0.2, "{center}");
+
+        camera.exposure = Exposure::new(400., 1./100., 16.);
+        let image = render(&camera, &world, 64, 1, SamplerKind::Sobol, Filter::Box(0.5), 0);
+        assert!((image.color(0, 0).g - 1.).abs() < 1e-12);
+        //A faster shutter freezes the sphere on one side of the center
+        camera.exposure = Exposure::new(10000., 1./10000., 16.);
+        let image = render(&camera, &world, 16, 1, SamplerKind::Sobol, Filter::Box(0.5), 0);
+        assert!((image.color(20, 20).g - 0.25).abs() < 1e-9 && image.color(16, 20).g.min(image.color(23, 20).g) < 1e-9);
+    }
+
+    #[test]
+    fn crop_window() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 2., 60., 0.2, 3.);
+        let mut world = World::new();
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 1., Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 }))));
+        let filter = Filter::Gaussian { radius : 1.5, alpha : 2. };
+        let full = render(&camera, &world, 4, 1, SamplerKind::Sobol, filter, 3);
+
+        //Same pixels in the window, nothing around it
+        let window = CropWindow::new(10, 5, 12, 8);
+        let crop = render_crop(&camera, &world, &window, 4, 1, SamplerKind::Sobol, filter, 3);
+        for j in 0..20 {
+            for i in 0..40 {
+                if (10..22).contains(&i) && (5..13).contains(&j) {
+                    assert!((crop.color(i, j).g - full.color(i, j).g).abs() < 1e-12 && crop.samples(i, j) == 4);
+                } else {
+                    assert!(crop.samples(i, j) == 0 && crop.color(i, j).g == 0.);
+                }
+            }
+        }
+
+        //Cut out of the image, and clipped to it
+        let cropped = crop.crop(&window);
+        assert_eq!((cropped.width(), cropped.height()), (12, 8));
+        assert_eq!(cropped.color(0, 0).g, crop.color(10, 5).g);
+        assert_eq!(cropped.color(11, 7).g, crop.color(21, 12).g);
+        let clipped = full.crop(&CropWindow::new(30, 15, 20, 20));
+        assert_eq!((clipped.width(), clipped.height()), (10, 5));
+    }
+
+    #[test]
+    fn alpha_and_transparent_background() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 2., 60., 0., 3.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.5, g: 0.5, b: 0.5 });
+        world.background_visible = false;
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 1., Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 }))));
+        let image = render(&camera, &world, 16, 1, SamplerKind::Sobol, Filter::Box(0.5), 0);
+
+        //The background lights the sphere but isn't seen around it, the edge is partly covered
+        assert!((image.alpha(20, 10) - 1.).abs() < 1e-12 && image.color(20, 10).g > 0.1);
+        assert!(image.alpha(0, 0) == 0. && image.color(0, 0).g == 0.);
+        assert!((0..20).any(|i| image.alpha(i, 10) > 0.05 && image.alpha(i, 10) < 0.95));
+
+        let mut png = vec![];
+        image.write_png(&mut png, &Display::default()).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        //Header, the offsets of the 20 lines and each line of 4 channels
+        let mut exr = vec![];
+        image.write_exr(&mut exr).unwrap();
+        assert_eq!(&exr[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        let lines = 20*(8 + 4*40*4);
+        let header = exr.len() - lines - 20*8;
+        assert_eq!(u64::from_le_bytes(exr[header..header + 8].try_into().unwrap()), (header + 20*8) as u64);
+
+        //A visible background is opaque
+        world.background_visible = true;
+        let image = render(&camera, &world, 4, 1, SamplerKind::Sobol, Filter::Box(0.5), 0);
+        assert!((image.alpha(0, 0) - 1.).abs() < 1e-12 && (image.color(0, 0).g - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stereo_pair() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 20, 2., 60., 0., 3.);
+        let mut world = World::new();
+        world.add_sphere(Point { x: 0., y: 0., z: -1. }, 0.2, Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0., g: 0., b: 0. }))));
+        let stereo = Stereo::new(0.3, 1., crate::camera::stereo::Rig::Parallel);
+        let (left, right) = render_stereo(&camera, &world, &stereo, 4, 1, SamplerKind::Sobol, Filter::Box(0.5), 0);
+
+        //The close sphere is more to the right in the image of the left eye
+        let darkest = |image : &Framebuffer| (0..20).min_by(|&a, &b| image.color(a, 5).g.total_cmp(&image.color(b, 5).g)).unwrap();
+        assert!(darkest(&left) > darkest(&right));
+
+        let side_by_side = Framebuffer::pack(&left, &right, Layout::SideBySide);
+        let top_bottom = Framebuffer::pack(&left, &right, Layout::TopBottom);
+        assert_eq!((side_by_side.width(), side_by_side.height(), top_bottom.width(), top_bottom.height()), (40, 10, 20, 20));
+        assert_eq!(side_by_side.color(25, 3).g, right.color(5, 3).g);
+        assert_eq!(top_bottom.color(5, 3).g, left.color(5, 3).g);
+        assert_eq!(top_bottom.color(5, 13).g, right.color(5, 3).g);
+    }
+
+    #[test]
+    fn same_seed_same_image() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 2., 60., 0.2, 3.);
+        let mut world = World::new();
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 1., Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 }))));
+
+        //Neither the threads nor the passes change the samples
+        let image = render(&camera, &world, 4, 1, SamplerKind::Sobol, Filter::Box(0.5), 9);
+        let mut settings = Progressive::new(4, 1);
+        settings.seed = 9;
+        let progressive = render_progressive(&camera, &world, 3, &settings, &AtomicBool::new(false), |_| ());
+        assert!(image.sums.iter().zip(&progressive.sums).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+
+        let other = render(&camera, &world, 4, 1, SamplerKind::Sobol, Filter::Box(0.5), 10);
+        assert!(image.sums.iter().zip(&other.sums).any(|(a, b)| a.r != b.r));
+    }
+
+    #[test]
+    fn adaptive_samples_the_noisy_pixels() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 2., 60., 0., 1.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.5, g: 0.5, b: 0.5 });
+        let grey = Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 })));
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 0.5, Arc::clone(&grey));
+        world.add_sphere(Point { x: 0., y: -100.5, z: -3. }, 100., grey);
+
+        //The uniform sky converges with the first samples, the sphere is partly hidden from it by the ground
+        let framebuffer = render_adaptive(&camera, &world, 2, &Adaptive::new(0.01, 64));
+        assert_eq!(framebuffer.samples(0, 0), 16);
+        assert!(framebuffer.error(0, 0) == 0.);
+        assert!(framebuffer.samples(20, 10) > 16 && framebuffer.samples(20, 10) <= 64);
+
+        let mut heatmap = vec![];
+        framebuffer.write_heatmap(&mut heatmap).unwrap();
+        let heatmap = String::from_utf8(heatmap).unwrap();
+        assert!(heatmap.starts_with("P3\n40 20\n255\n"));
+        assert_eq!(heatmap.lines().count(), 3 + 40*20);
+    }
+}
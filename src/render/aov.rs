@@ -0,0 +1,241 @@
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, sync::Arc};
+
+use crate::{world::World, color::Color, point::Point, ray::PathRecord};
+use super::{Framebuffer, Tile};
+
+//What the camera rays of a pixel hit first, averaged over its samples
+#[derive(Debug, Clone, Copy)]
+pub struct AovPixel {
+    pub depth : f64, //Distance to the camera, infinite for the background
+    pub position : Point<f64>, //Zero for the background
+    pub normal : Point<f64>, //Shading normal, zero for the background
+    pub albedo : Color<f64>,
+    pub object_id : u32, //0 for the background, then from 1 in the order of the surfaces of the world
+    pub material_id : u32, //0 for the background, then from 1 in the order the textures first appear
+    pub direct : Color<f64>, //Lights and background seen directly or after one bounce
+    pub indirect : Color<f64>, //The rest of the light, so direct + indirect is the beauty
+}
+
+//Sums of the records of the samples of a pixel
+#[derive(Debug, Clone, Copy)]
+pub(super) struct AovSum {
+    samples : u32,
+    hits : u32,
+    depth : f64,
+    position : Point<f64>,
+    normal : Point<f64>,
+    albedo : Color<f64>,
+    direct : Color<f64>,
+    indirect : Color<f64>,
+    object : usize, //Addresses of the surface and the texture the first sample to hit something hits, 0 until then
+    material : usize,
+}
+
+impl AovSum {
+    pub(super) fn new() -> AovSum {
+        AovSum {
+            samples : 0,
+            hits : 0,
+            depth : 0.,
+            position : Point { x: 0., y: 0., z: 0. },
+            normal : Point { x: 0., y: 0., z: 0. },
+            albedo : Color { r: 0., g: 0., b: 0. },
+            direct : Color { r: 0., g: 0., b: 0. },
+            indirect : Color { r: 0., g: 0., b: 0. },
+            object : 0,
+            material : 0,
+        }
+    }
+
+    //The light is exposed as in the beauty
+    pub(super) fn add(&mut self, record : &PathRecord, exposure : f64) {
+        self.samples += 1;
+        self.albedo = self.albedo + record.albedo;
+        self.direct = self.direct + record.direct*exposure;
+        self.indirect = self.indirect + record.indirect*exposure;
+        if let Some(first) = &record.first {
+            self.hits += 1;
+            self.depth += first.depth;
+            self.position = self.position + first.p;
+            self.normal = self.normal + first.normal;
+            if self.object == 0 {
+                self.object = first.surface as *const _ as usize;
+                self.material = Arc::as_ptr(&first.texture) as usize;
+            }
+        }
+    }
+}
+
+//Auxiliary buffers of the image, recorded with the samples of the beauty, row by row
+//They aren't filtered, each pixel averages the samples taken in it
+pub struct Aovs {
+    width : u32,
+    height : u32,
+    sums : Vec<AovSum>,
+    ids : Vec<(u32, u32)>, //Object and material, those of the first sample of the pixel to hit something
+    objects : HashMap<usize, u32>, //Ids found back from the addresses of the surfaces and textures
+    materials : HashMap<usize, u32>,
+}
+
+impl Aovs {
+    pub(super) fn new(world : &World, width : u32, height : u32) -> Aovs {
+        let (mut objects, mut materials) = (HashMap::new(), HashMap::new());
+        for (surface, texture) in world.leaves() {
+            let next = objects.len() as u32 + 1;
+            objects.insert(surface as *const _ as usize, next);
+            let next = materials.len() as u32 + 1;
+            materials.entry(Arc::as_ptr(texture) as usize).or_insert(next);
+        }
+        let size = (width*height) as usize;
+        Aovs { width, height, sums : vec![AovSum::new(); size], ids : vec![(0, 0); size], objects, materials }
+    }
+
+    //Add the sums of a tile rendered apart
+    pub(super) fn add_tile(&mut self, tile : &Tile, sums : &[AovSum]) {
+        for j in 0..tile.height {
+            for i in 0..tile.width {
+                let index = ((tile.y + j)*self.width + tile.x + i) as usize;
+                let (sum, add) = (&mut self.sums[index], sums[(j*tile.width + i) as usize]);
+                if sum.object == 0 && add.object != 0 {
+                    sum.object = add.object;
+                    sum.material = add.material;
+                    self.ids[index] = (self.objects.get(&add.object).copied().unwrap_or(0), self.materials.get(&add.material).copied().unwrap_or(0));
+                }
+                sum.samples += add.samples;
+                sum.hits += add.hits;
+                sum.depth += add.depth;
+                sum.position = sum.position + add.position;
+                sum.normal = sum.normal + add.normal;
+                sum.albedo = sum.albedo + add.albedo;
+                sum.direct = sum.direct + add.direct;
+                sum.indirect = sum.indirect + add.indirect;
+            }
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, i : u32, j : u32) -> AovPixel {
+        self.at((j*self.width + i) as usize)
+    }
+
+    pub(super) fn at(&self, index : usize) -> AovPixel {
+        let sum = &self.sums[index];
+        let scale = 1./sum.samples.max(1) as f64;
+        let (object_id, material_id) = self.ids[index];
+        //A pixel is of the background only if all its rays miss
+        let (depth, position, normal) = match sum.hits {
+            0 => (f64::INFINITY, sum.position, sum.normal),
+            hits => (sum.depth/hits as f64, sum.position*(1./hits as f64), if sum.normal.near_zero() { sum.normal } else { sum.normal.unit() }),
+        };
+        AovPixel {
+            depth,
+            position,
+            normal,
+            albedo : sum.albedo*scale,
+            object_id,
+            material_id,
+            direct : sum.direct*scale,
+            indirect : sum.indirect*scale,
+        }
+    }
+
+    //One PFM file per buffer, named prefix.layer.pfm, the beauty and its sample count with them
+    pub fn save(&self, prefix : &str, beauty : &Framebuffer) -> io::Result<()> {
+        assert_eq!((self.width, self.height), (beauty.width(), beauty.height()), "The buffers don't have the size of the image");
+        let color = |color : Color<f64>| [color.r, color.g, color.b];
+        let point = |point : Point<f64>| [point.x, point.y, point.z];
+        self.save_layer(prefix, "beauty", |i, j, _| color(beauty.color(i, j)).to_vec())?;
+        self.save_layer(prefix, "depth", |_, _, pixel| vec![pixel.depth])?;
+        self.save_layer(prefix, "position", |_, _, pixel| point(pixel.position).to_vec())?;
+        self.save_layer(prefix, "normal", |_, _, pixel| point(pixel.normal).to_vec())?;
+        self.save_layer(prefix, "albedo", |_, _, pixel| color(pixel.albedo).to_vec())?;
+        self.save_layer(prefix, "object_id", |_, _, pixel| vec![pixel.object_id as f64])?;
+        self.save_layer(prefix, "material_id", |_, _, pixel| vec![pixel.material_id as f64])?;
+        self.save_layer(prefix, "direct", |_, _, pixel| color(pixel.direct).to_vec())?;
+        self.save_layer(prefix, "indirect", |_, _, pixel| color(pixel.indirect).to_vec())?;
+        self.save_layer(prefix, "samples", |i, j, _| vec![beauty.samples(i, j) as f64])
+    }
+
+    fn save_layer<F : Fn(u32, u32, &AovPixel) -> Vec<f64>>(&self, prefix : &str, layer : &str, value : F) -> io::Result<()> {
+        let channels = value(0, 0, &self.pixel(0, 0)).len();
+        let mut out = BufWriter::new(File::create(format!("{prefix}.{layer}.pfm"))?);
+        write_pfm_header(&mut out, self.width, self.height, channels)?;
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                write_pfm_values(&mut out, &value(i, j, &self.pixel(i, j)))?;
+            }
+        }
+        out.flush()
+    }
+}
+
+//Portable float map : "PF" for colors or "Pf" for grey, the size, then a negative scale for little-endian floats
+//The rows go from the bottom
+fn write_pfm_header<W : Write>(out : &mut W, width : u32, height : u32, channels : usize) -> io::Result<()> {
+    write!(out, "{}\n{width} {height}\n-1.0\n", if channels == 3 { "PF" } else { "Pf" })
+}
+
+fn write_pfm_values<W : Write>(out : &mut W, values : &[f64]) -> io::Result<()> {
+    for value in values {
+        out.write_all(&(*value as f32).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Camera, sampler::SamplerKind, render::render_aovs, render::filter::Filter, world::Background, material::{Texture, diffuse::Diffuse, emissive::Emissive}};
+
+    #[test]
+    fn ids_depths_and_lighting() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 32, 1., 60., 0., 3.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.5, g: 0.5, b: 0.5 });
+        let grey = Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 })));
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 0.8, Arc::clone(&grey));
+        world.add_sphere(Point { x: 0., y: -100.8, z: -3. }, 100., grey);
+        world.add_sphere(Point { x: 1.2, y: 0.8, z: -3. }, 0.3, Arc::new(Texture::Emissive(Emissive::new(Color { r: 4., g: 4., b: 4. }, false))));
+
+        let (beauty, aovs) = render_aovs(&camera, &world, 64, 2, SamplerKind::Sobol, Filter::Box(0.5), 1);
+        let (sky, sphere, ground) = (aovs.pixel(0, 0), aovs.pixel(16, 16), aovs.pixel(16, 31));
+        assert_eq!((sky.object_id, sky.material_id, sky.depth), (0, 0, f64::INFINITY));
+        assert_eq!((sphere.object_id, sphere.material_id, ground.object_id, ground.material_id), (1, 1, 2, 1));
+        assert!((sphere.depth - 2.2).abs() < 0.05 && (sphere.position.z + 2.2).abs() < 0.05 && (sphere.normal.z - 1.).abs() < 0.05);
+        assert!((sky.direct.g - 0.5).abs() < 1e-12 && sky.indirect.g == 0.);
+        //The ground under the sphere gets light back from it
+        assert!(ground.indirect.g > 0. && ground.direct.g > 0.);
+
+        //The buffers split the light of the same samples
+        for j in 0..32 {
+            for i in 0..32 {
+                let pixel = aovs.pixel(i, j);
+                assert!(((pixel.direct + pixel.indirect).luminance() - beauty.color(i, j).luminance()).abs() < 1e-9);
+            }
+        }
+
+        //Where the camera doesn't see the background, it isn't in the buffers either
+        world.background_visible = false;
+        let (_, aovs) = render_aovs(&camera, &world, 4, 2, SamplerKind::Sobol, Filter::Box(0.5), 1);
+        let sky = aovs.pixel(0, 0);
+        assert_eq!((sky.albedo.g, sky.direct.g, sky.depth, sky.normal.z), (0., 0., f64::INFINITY, 0.));
+
+        let prefix = std::env::temp_dir().join(format!("aov_test_{}", std::process::id()));
+        let prefix = prefix.to_str().unwrap();
+        aovs.save(prefix, &beauty).unwrap();
+        let depth = std::fs::read(format!("{prefix}.depth.pfm")).unwrap();
+        let normal = std::fs::read(format!("{prefix}.normal.pfm")).unwrap();
+        assert!(depth.starts_with(b"Pf\n32 32\n-1.0\n") && depth.len() == 14 + 32*32*4);
+        assert!(normal.starts_with(b"PF\n32 32\n-1.0\n") && normal.len() == 14 + 32*32*12);
+        for layer in ["beauty", "depth", "position", "normal", "albedo", "object_id", "material_id", "direct", "indirect", "samples"] {
+            std::fs::remove_file(format!("{prefix}.{layer}.pfm")).unwrap();
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::{fmt, fs, io::{self, BufWriter, Write}, path::Path, sync::atomic::AtomicBool};
+
+use crate::{Camera, world::World, color::Color, sampler::SamplerKind};
+use super::{Framebuffer, Progressive, continue_progressive, filter::Filter};
+
+const MAGIC : &[u8; 4] = b"RTCK";
+const VERSION : u32 = 4;
+const HEADER_SIZE : usize = 4 + 4 + 8 + 4*4;
+const PIXEL_SIZE : usize = 3*8 + 8 + 8 + 8 + 4;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    //The file isn't a checkpoint or is damaged
+    Invalid(String),
+    //The checkpoint was made from another scene or with other settings
+    SceneChanged { expected : u64, found : u64 },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "I/O error : {err}"),
+            CheckpointError::Invalid(msg) => write!(f, "Invalid checkpoint : {msg}"),
+            CheckpointError::SceneChanged { expected, found } => write!(f, "The scene changed since the checkpoint (hash {found:016x} instead of {expected:016x})"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err : io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+//FNV-1a hash of everything written in it
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes : &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+//Hash of what the samples of an image depend on, to check a checkpoint or partial renders belong to the same image
+//source stands for the scene : the bytes of the file it was loaded from, or a name for a scene built in code
+//The settings are written value by value so the hash stays the same from a build to the next
+pub fn scene_hash(source : &[u8], image_width : u32, image_height : u32, sampler : SamplerKind, filter : Filter) -> u64 {
+    let mut hash = Fnv::new();
+    hash.write(&(source.len() as u64).to_le_bytes());
+    hash.write(source);
+    hash.write(&image_width.to_le_bytes());
+    hash.write(&image_height.to_le_bytes());
+    hash.write(&[sampler.code() as u8]);
+    let (kind, params) = match filter {
+        Filter::Box(radius) => (0, [radius, 0., 0.]),
+        Filter::Tent(radius) => (1, [radius, 0., 0.]),
+        Filter::Gaussian { radius, alpha } => (2, [radius, alpha, 0.]),
+        Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+        Filter::Lanczos(radius) => (4, [radius, 0., 0.]),
+    };
+    hash.write(&[kind]);
+    for param in params {
+        hash.write(&param.to_le_bytes());
+    }
+    hash.0
+}
+
+//A render in progress as stored on disk
+pub struct Checkpoint {
+    pub scene_hash : u64,
+    pub target_samples : u32,
+    pub samples_per_pass : u32,
+    pub framebuffer : Framebuffer,
+}
+
+//The file is written next to its destination then renamed, so a crash while saving keeps the previous checkpoint
+//Layout (little endian) : magic, version, scene hash, width, height, target samples, samples per pass
+//then for each pixel the weighted sums of red, green and blue, the sum of the weights,
+//the weighted sum of the alphas, the sum of the squared luminances (f64) and the number of samples (u32)
+pub fn save<P : AsRef<Path>>(path : P, scene_hash : u64, settings : &Progressive, framebuffer : &Framebuffer) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut out = BufWriter::new(fs::File::create(&temporary)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&scene_hash.to_le_bytes())?;
+    for value in [framebuffer.width, framebuffer.height, settings.target_samples, settings.samples_per_pass] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    for (index, samples) in framebuffer.samples.iter().enumerate() {
+        let sum = framebuffer.sums[index];
+        for value in [sum.r, sum.g, sum.b, framebuffer.weights[index], framebuffer.alphas[index], framebuffer.squares[index]] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&samples.to_le_bytes())?;
+    }
+    out.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+pub fn load<P : AsRef<Path>>(path : P) -> Result<Checkpoint, CheckpointError> {
+    let data = fs::read(path)?;
+    if data.len() < HEADER_SIZE || &data[..4] != MAGIC {
+        return Err(CheckpointError::Invalid(String::from("Not a checkpoint file")));
+    }
+    let u32_at = |pos : usize| u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+    if u32_at(4) != VERSION {
+        return Err(CheckpointError::Invalid(format!("Unknown version {}", u32_at(4))));
+    }
+    let scene_hash = u64::from_le_bytes(data[8..16].try_into().expect("8 bytes"));
+    let (width, height, target_samples, samples_per_pass) = (u32_at(16), u32_at(20), u32_at(24), u32_at(28));
+
+    let pixels = width as usize*height as usize;
+    if data.len() != HEADER_SIZE + pixels*PIXEL_SIZE {
+        return Err(CheckpointError::Invalid(format!("{} bytes instead of {} for a {width}x{height} image", data.len(), HEADER_SIZE + pixels*PIXEL_SIZE)));
+    }
+    let mut framebuffer = Framebuffer::new(width, height);
+    for (index, pixel) in data[HEADER_SIZE..].chunks_exact(PIXEL_SIZE).enumerate() {
+        let f64_at = |pos : usize| f64::from_le_bytes(pixel[pos..pos + 8].try_into().expect("8 bytes"));
+        framebuffer.sums[index] = Color { r: f64_at(0), g: f64_at(8), b: f64_at(16) };
+        framebuffer.weights[index] = f64_at(24);
+        framebuffer.alphas[index] = f64_at(32);
+        framebuffer.squares[index] = f64_at(40);
+        framebuffer.samples[index] = u32::from_le_bytes(pixel[48..52].try_into().expect("4 bytes"));
+    }
+
+    Ok(Checkpoint {
+        scene_hash,
+        target_samples,
+        samples_per_pass,
+        framebuffer,
+    })
+}
+
+//Progressive render saving a checkpoint at path with each snapshot, source is what the scene was built from (see scene_hash())
+//A checkpoint that can't be written is reported but doesn't stop the render
+#[allow(clippy::too_many_arguments)]
+pub fn render<P : AsRef<Path>, F : FnMut(&Framebuffer)>(camera : &Camera, world : &World, source : &[u8], threads : usize, settings : &Progressive, cancel : &AtomicBool, path : P, snapshot : F) -> Framebuffer {
+    let framebuffer = Framebuffer::new(camera.image_width, camera.image_height);
+    let hash = scene_hash(source, camera.image_width, camera.image_height, settings.sampler, settings.filter);
+    let saver = saver(path.as_ref(), hash, settings, snapshot);
+    continue_progressive(camera, world, threads, settings, cancel, framebuffer, saver)
+}
+
+//Continue the render saved at path until every pixel has the target of the settings
+//The target can be raised to add samples to a finished render
+#[allow(clippy::too_many_arguments)]
+pub fn resume<P : AsRef<Path>, F : FnMut(&Framebuffer)>(camera : &Camera, world : &World, source : &[u8], threads : usize, settings : &Progressive, cancel : &AtomicBool, path : P, snapshot : F) -> Result<Framebuffer, CheckpointError> {
+    let checkpoint = load(&path)?;
+    let hash = scene_hash(source, camera.image_width, camera.image_height, settings.sampler, settings.filter);
+    if checkpoint.scene_hash != hash {
+        return Err(CheckpointError::SceneChanged { expected: checkpoint.scene_hash, found: hash });
+    }
+    let saver = saver(path.as_ref(), hash, settings, snapshot);
+    Ok(continue_progressive(camera, world, threads, settings, cancel, checkpoint.framebuffer, saver))
+}
+
+//Snapshot callback saving the checkpoint before calling snapshot()
+fn saver<'a, F : FnMut(&Framebuffer) + 'a>(path : &'a Path, hash : u64, settings : &'a Progressive, mut snapshot : F) -> impl FnMut(&Framebuffer) + 'a {
+    move |framebuffer| {
+        if let Err(err) = save(path, hash, settings, framebuffer) {
+            eprintln!("Couldn't save the checkpoint {} : {err}", path.display());
+        }
+        snapshot(framebuffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use crate::{point::Point, world::Background};
+
+    #[test]
+    fn interrupted_and_resumed() {
+        let path = std::env::temp_dir().join(format!("checkpoint_test_{}.rtck", std::process::id()));
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 2., 60., 0., 1.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.5, g: 0.5, b: 0.5 });
+        let settings = Progressive::new(6, 2);
+
+        //Stopped after the first pass
+        let cancel = AtomicBool::new(false);
+        render(&camera, &world, b"gray", 2, &settings, &cancel, &path, |_| cancel.store(true, Ordering::Relaxed));
+        let checkpoint = load(&path).unwrap();
+        //The hash doesn't depend on the build, a checkpoint can be resumed by another version of the renderer
+        assert_eq!(checkpoint.scene_hash, 0x1f473c4977009ae4);
+        assert_eq!((checkpoint.target_samples, checkpoint.framebuffer.samples(3, 4)), (6, 2));
+
+        let not_cancelled = AtomicBool::new(false);
+        let framebuffer = resume(&camera, &world, b"gray", 2, &settings, &not_cancelled, &path, |_| ()).unwrap();
+        assert_eq!(framebuffer.min_samples(), 6);
+        assert_eq!(load(&path).unwrap().framebuffer.samples(39, 19), 6);
+        assert!((framebuffer.color(10, 10).r - 0.5).abs() < 1e-12);
+
+        world.background = Background::Uniform(Color { r: 0.6, g: 0.5, b: 0.5 });
+        let changed = resume(&camera, &world, b"red", 2, &settings, &not_cancelled, &path, |_| ());
+        assert!(matches!(changed, Err(CheckpointError::SceneChanged { .. })));
+        let mut other_filter = Progressive::new(6, 2);
+        other_filter.filter = Filter::Tent(1.);
+        let changed = resume(&camera, &world, b"gray", 2, &other_filter, &not_cancelled, &path, |_| ());
+        assert!(matches!(changed, Err(CheckpointError::SceneChanged { .. })));
+
+        fs::write(&path, b"RTCK").unwrap();
+        assert!(matches!(load(&path), Err(CheckpointError::Invalid(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
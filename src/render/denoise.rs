@@ -0,0 +1,195 @@
+use std::thread;
+
+use crate::{color::Color, point::Point};
+use super::{Framebuffer, aov::Aovs};
+
+//Weights of the 5 taps of the à-trous kernel (B3 spline)
+const KERNEL : [f64; 5] = [1./16., 1./4., 3./8., 1./4., 1./16.];
+
+//What the camera rays hit first in each pixel, a view of the auxiliary buffers of the render
+//The edges of these buffers are the edges the denoiser keeps
+pub struct Features<'a> {
+    aovs : &'a Aovs,
+}
+
+impl<'a> Features<'a> {
+    pub fn new(aovs : &'a Aovs) -> Features<'a> {
+        Features { aovs }
+    }
+
+    pub fn albedo(&self, i : u32, j : u32) -> Color<f64> {
+        self.aovs.pixel(i, j).albedo
+    }
+
+    //Zero where the rays see the background
+    pub fn normal(&self, i : u32, j : u32) -> Point<f64> {
+        self.aovs.pixel(i, j).normal
+    }
+
+    //Distance to the camera, infinite for the background
+    pub fn depth(&self, i : u32, j : u32) -> f64 {
+        self.aovs.pixel(i, j).depth
+    }
+}
+
+//Settings of the denoiser
+pub struct Denoise {
+    pub iterations : u32, //Each one doubles the spacing of the taps, 5 reach 62 pixels away
+    pub sigma_luminance : f64, //In standard deviations of the noise of the pixel
+    pub normal_power : f64, //The higher, the less pixels with different normals are mixed
+    pub sigma_depth : f64, //Relative difference of depth per pixel between the taps
+}
+
+impl Default for Denoise {
+    fn default() -> Denoise {
+        Denoise {
+            iterations : 5,
+            sigma_luminance : 4.,
+            normal_power : 64.,
+            sigma_depth : 0.1,
+        }
+    }
+}
+
+//Edge-avoiding à-trous wavelet filter (Dammertz et al.) guided by the variance of the pixels as in SVGF
+//The lighting is filtered apart from the albedo, so the textures stay sharp
+//The result has one sample of weight one in each pixel
+pub fn denoise(framebuffer : &Framebuffer, features : &Features, settings : &Denoise, threads : usize) -> Framebuffer {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    assert_eq!((width, height), (features.aovs.width(), features.aovs.height()), "The features don't have the size of the image");
+    let size = (width*height) as usize;
+
+    //Lighting and variance of its mean
+    let divide = |a : f64, b : f64| if b > 1e-3 { a/b } else { a };
+    let mut lighting : Vec<Color<f64>> = (0..size).map(|index| {
+        let (color, albedo) = (framebuffer.mean(index), features.aovs.at(index).albedo);
+        Color { r: divide(color.r, albedo.r), g: divide(color.g, albedo.g), b: divide(color.b, albedo.b) }
+    }).collect();
+    let variance : Vec<f64> = (0..size).map(|index| {
+        let n = framebuffer.samples[index] as f64;
+        let mean = framebuffer.mean(index).luminance();
+        let variance = if n < 2. { 1. } else { ((framebuffer.squares[index]/n - mean*mean)/(n - 1.)).max(0.) };
+        divide(variance, features.aovs.at(index).albedo.luminance().powi(2))
+    }).collect();
+    //The variance of a few samples is too noisy itself, it is averaged over the surface around the pixel
+    let mut variance : Vec<f64> = (0..size).map(|pixel| {
+        let (i, j) = ((pixel as u32%width) as i64, (pixel as u32/width) as i64);
+        let (mut sum, mut sum_weights) = (0., 0.);
+        for y in (j - 3).max(0)..(j + 4).min(height as i64) {
+            for x in (i - 3).max(0)..(i + 4).min(width as i64) {
+                let other = (y*width as i64 + x) as usize;
+                let weight = geometry_weight(features, pixel, other, 1., settings);
+                sum += weight*variance[other];
+                sum_weights += weight;
+            }
+        }
+        if sum_weights > 1e-12 { sum/sum_weights } else { variance[pixel] }
+    }).collect();
+
+    let band = (height.div_ceil(threads.max(1) as u32).max(1)*width) as usize;
+    for iteration in 0..settings.iterations {
+        let step = 1i64 << iteration;
+        let mut next_lighting = lighting.clone();
+        let mut next_variance = variance.clone();
+        thread::scope(|scope| {
+            for (number, (out_lighting, out_variance)) in next_lighting.chunks_mut(band).zip(next_variance.chunks_mut(band)).enumerate() {
+                let (lighting, variance) = (&lighting, &variance);
+                scope.spawn(move || {
+                    for index in 0..out_lighting.len() {
+                        let pixel = number*band + index;
+                        let (i, j) = ((pixel as u32%width) as i64, (pixel as u32/width) as i64);
+                        let luminance = lighting[pixel].luminance();
+                        let sigma = settings.sigma_luminance*variance[pixel].sqrt() + 1e-6;
+
+                        let (mut sum, mut sum_variance, mut sum_weights) = (Color { r: 0., g: 0., b: 0. }, 0., 0.);
+                        for (dy, ky) in KERNEL.iter().enumerate() {
+                            for (dx, kx) in KERNEL.iter().enumerate() {
+                                let (x, y) = (i + (dx as i64 - 2)*step, j + (dy as i64 - 2)*step);
+                                if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                                    continue;
+                                }
+                                let other = (y*width as i64 + x) as usize;
+                                let weight = kx*ky
+                                    *(-(lighting[other].luminance() - luminance).abs()/sigma).exp()
+                                    *geometry_weight(features, pixel, other, step as f64, settings);
+                                sum = sum + lighting[other]*weight;
+                                sum_variance += weight*weight*variance[other];
+                                sum_weights += weight;
+                            }
+                        }
+                        //A pixel unlike all its neighbours, even itself by its normal, is kept
+                        if sum_weights > 1e-12 {
+                            out_lighting[index] = sum*(1./sum_weights);
+                            out_variance[index] = sum_variance/(sum_weights*sum_weights);
+                        }
+                    }
+                });
+            }
+        });
+        lighting = next_lighting;
+        variance = next_variance;
+    }
+
+    let mut denoised = Framebuffer::new(width, height);
+    let multiply = |a : f64, b : f64| if b > 1e-3 { a*b } else { a };
+    for (index, light) in lighting.iter().enumerate() {
+        let albedo = features.aovs.at(index).albedo;
+        denoised.sums[index] = Color { r: multiply(light.r, albedo.r), g: multiply(light.g, albedo.g), b: multiply(light.b, albedo.b) };
+        denoised.weights[index] = 1.;
+        denoised.alphas[index] = framebuffer.alpha(index as u32%width, index as u32/width);
+        denoised.samples[index] = framebuffer.samples[index];
+    }
+    denoised
+}
+
+//How much two pixels seem to be on the same surface
+fn geometry_weight(features : &Features, pixel : usize, other : usize, step : f64, settings : &Denoise) -> f64 {
+    let (pixel, other) = (features.aovs.at(pixel), features.aovs.at(other));
+    let (depth, other_depth) = (pixel.depth, other.depth);
+    match (depth.is_finite(), other_depth.is_finite()) {
+        (false, false) => 1.,
+        (true, true) => {
+            let normal = (pixel.normal&other.normal).max(0.).powf(settings.normal_power);
+            normal*(-(depth - other_depth).abs()/(settings.sigma_depth*step*depth + 1e-9)).exp()
+        },
+        _ => 0.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{Camera, world::{World, Background}, sampler::SamplerKind, render::{render, render_aovs}, render::filter::Filter, material::{Texture, diffuse::Diffuse}};
+
+    #[test]
+    fn less_noise_same_edges() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 64, 1., 60., 0., 3.);
+        let mut world = World::new();
+        world.background = Background::Uniform(Color { r: 0.8, g: 0.8, b: 0.8 });
+        let grey = Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 })));
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 0.8, Arc::clone(&grey));
+        world.add_sphere(Point { x: 0., y: -100.8, z: -3. }, 100., grey);
+
+        //The features come from the same few samples, stratified so the edges they keep are clean
+        let (noisy, aovs) = render_aovs(&camera, &world, 4, 2, SamplerKind::Stratified, Filter::Box(0.5), 1);
+        let reference = render(&camera, &world, 256, 2, SamplerKind::Sobol, Filter::Box(0.5), 2);
+        let features = Features::new(&aovs);
+        assert!(features.depth(0, 0).is_infinite() && (features.depth(32, 32) - 2.2).abs() < 0.05);
+        assert!((features.normal(32, 32).z - 1.).abs() < 0.05);
+
+        let denoised = denoise(&noisy, &features, &Denoise::default(), 2);
+        let error = |image : &Framebuffer| {
+            let mut error = 0.;
+            for j in 0..64 {
+                for i in 0..64 {
+                    error += (image.color(i, j).luminance() - reference.color(i, j).luminance()).powi(2);
+                }
+            }
+            error
+        };
+        assert!(error(&denoised) < error(&noisy)/4.);
+        //The sky isn't mixed with the sphere
+        assert!((denoised.color(0, 0).g - 0.8).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{fmt, fs, io::{self, BufWriter, Write}, path::Path, sync::Mutex};
+
+use crate::{Camera, world::World, color::{Color, display::WorkingSpace}, sampler::SamplerKind};
+use super::{Framebuffer, Pass, Tile, checkpoint::scene_hash, filter::Filter};
+
+const MAGIC : &[u8; 4] = b"RTPT";
+const VERSION : u32 = 6;
+const HEADER_SIZE : usize = 4 + 4 + 8 + 8 + 14*4;
+const PIXEL_SIZE : usize = 3*8 + 8 + 8 + 8 + 4;
+
+#[derive(Debug)]
+pub enum PartialError {
+    Io(io::Error),
+    //The file isn't a partial render or is damaged, or the slice doesn't fit in the image
+    Invalid(String),
+    //The partial renders come from different scenes, image sizes or working spaces
+    Mismatch(String),
+    //Two partial renders hold the same samples of some pixels
+    Overlap(String),
+}
+
+impl fmt::Display for PartialError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartialError::Io(err) => write!(f, "I/O error : {err}"),
+            PartialError::Invalid(msg) => write!(f, "Invalid partial render : {msg}"),
+            PartialError::Mismatch(msg) => write!(f, "Partial renders of different images : {msg}"),
+            PartialError::Overlap(msg) => write!(f, "Overlapping partial renders : {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for PartialError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PartialError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PartialError {
+    fn from(err : io::Error) -> Self {
+        PartialError::Io(err)
+    }
+}
+
+//The part of a render done by one process : a rectangle of pixels and a range of their samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slice {
+    pub x : u32,
+    pub y : u32,
+    pub width : u32,
+    pub height : u32,
+    pub first_sample : u32, //Index of the first sample of the range
+    pub samples : u32,
+    pub seed : u64, //Same seed, sampler and slice, same result
+}
+
+impl Slice {
+    pub fn new(x : u32, y : u32, width : u32, height : u32, first_sample : u32, samples : u32, seed : u64) -> Slice {
+        Slice { x, y, width, height, first_sample, samples, seed }
+    }
+
+    //A range of samples of the whole image
+    pub fn samples_of(camera : &Camera, first_sample : u32, samples : u32, seed : u64) -> Slice {
+        Slice::new(0, 0, camera.image_width, camera.image_height, first_sample, samples, seed)
+    }
+
+    fn region(&self) -> Tile {
+        Tile { x: self.x, y: self.y, width: self.width, height: self.height }
+    }
+
+    //True if both slices compute the same samples of some pixels
+    //The ends are in u64, the slices come from files
+    fn overlaps(&self, other : &Slice) -> bool {
+        let before = |start : u32, end : (u32, u32)| (start as u64) < end.0 as u64 + end.1 as u64;
+        self.seed == other.seed
+            && before(self.x, (other.x, other.width)) && before(other.x, (self.x, self.width))
+            && before(self.y, (other.y, other.height)) && before(other.y, (self.y, self.height))
+            && before(self.first_sample, (other.first_sample, other.samples)) && before(other.first_sample, (self.first_sample, self.samples))
+    }
+}
+
+//The accumulated samples of a slice
+pub struct Partial {
+    pub scene_hash : u64,
+    pub image_width : u32,
+    pub image_height : u32,
+    pub slice : Slice,
+    pub sampler : SamplerKind, //With the slice, enough to render the same samples again
+    pub working_space : WorkingSpace, //The space of the sums, for the display of the merged image
+    area : Tile, //The rectangle of the slice grown by the radius of the filter, its samples are splatted on it
+    sums : Vec<Color<f64>>, //Row by row over the area
+    weights : Vec<f64>,
+    alphas : Vec<f64>,
+    squares : Vec<f64>,
+    samples : Vec<u32>,
+}
+
+impl Partial {
+    //Layout (little endian) : magic, version, scene hash, seed, sampler, working space, image width and height,
+    //x, y, width and height of the slice, first sample, number of samples, x, y, width and height of the area
+    //then for each pixel of the area the weighted sums of red, green and blue, the sum of the weights,
+    //the weighted sum of the alphas, the sum of the squared luminances (f64) and the number of samples (u32)
+    pub fn save<P : AsRef<Path>>(&self, path : P) -> io::Result<()> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&self.scene_hash.to_le_bytes())?;
+        out.write_all(&self.slice.seed.to_le_bytes())?;
+        let slice = &self.slice;
+        let area = &self.area;
+        for value in [self.sampler.code(), self.working_space.code(), self.image_width, self.image_height, slice.x, slice.y, slice.width, slice.height, slice.first_sample, slice.samples, area.x, area.y, area.width, area.height] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        for (index, samples) in self.samples.iter().enumerate() {
+            let sum = self.sums[index];
+            for value in [sum.r, sum.g, sum.b, self.weights[index], self.alphas[index], self.squares[index]] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&samples.to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    pub fn load<P : AsRef<Path>>(path : P) -> Result<Partial, PartialError> {
+        let data = fs::read(path)?;
+        if data.len() < HEADER_SIZE || &data[..4] != MAGIC {
+            return Err(PartialError::Invalid(String::from("Not a partial render file")));
+        }
+        let u32_at = |pos : usize| u32::from_le_bytes(data[pos..pos + 4].try_into().expect("4 bytes"));
+        let u64_at = |pos : usize| u64::from_le_bytes(data[pos..pos + 8].try_into().expect("8 bytes"));
+        if u32_at(4) != VERSION {
+            return Err(PartialError::Invalid(format!("Unknown version {}", u32_at(4))));
+        }
+        let sampler = SamplerKind::from_code(u32_at(24)).ok_or_else(|| PartialError::Invalid(format!("Unknown sampler {}", u32_at(24))))?;
+        let working_space = WorkingSpace::from_code(u32_at(28)).ok_or_else(|| PartialError::Invalid(format!("Unknown working space {}", u32_at(28))))?;
+        let slice = Slice::new(u32_at(40), u32_at(44), u32_at(48), u32_at(52), u32_at(56), u32_at(60), u64_at(16));
+        let area = Tile { x: u32_at(64), y: u32_at(68), width: u32_at(72), height: u32_at(76) };
+        let (image_width, image_height) = (u32_at(32), u32_at(36));
+        if area.x as u64 + area.width as u64 > image_width as u64 || area.y as u64 + area.height as u64 > image_height as u64 {
+            return Err(PartialError::Invalid(String::from("The slice goes out of the image")));
+        }
+        let end = |start : u32, size : u32| start as u64 + size as u64;
+        if slice.x < area.x || slice.y < area.y || end(slice.x, slice.width) > end(area.x, area.width) || end(slice.y, slice.height) > end(area.y, area.height) {
+            return Err(PartialError::Invalid(String::from("The slice goes out of its area")));
+        }
+
+        let pixels = area.width as usize*area.height as usize;
+        let size = pixels.checked_mul(PIXEL_SIZE).and_then(|size| size.checked_add(HEADER_SIZE));
+        if size != Some(data.len()) {
+            return Err(PartialError::Invalid(format!("{} bytes for an area of {}x{} pixels", data.len(), area.width, area.height)));
+        }
+        let mut sums = Vec::with_capacity(pixels);
+        let mut weights = Vec::with_capacity(pixels);
+        let mut alphas = Vec::with_capacity(pixels);
+        let mut squares = Vec::with_capacity(pixels);
+        let mut samples = Vec::with_capacity(pixels);
+        for pixel in data[HEADER_SIZE..].chunks_exact(PIXEL_SIZE) {
+            let f64_at = |pos : usize| f64::from_le_bytes(pixel[pos..pos + 8].try_into().expect("8 bytes"));
+            sums.push(Color { r: f64_at(0), g: f64_at(8), b: f64_at(16) });
+            weights.push(f64_at(24));
+            alphas.push(f64_at(32));
+            squares.push(f64_at(40));
+            samples.push(u32::from_le_bytes(pixel[48..52].try_into().expect("4 bytes")));
+        }
+
+        Ok(Partial {
+            scene_hash : u64_at(8),
+            image_width,
+            image_height,
+            slice,
+            sampler,
+            working_space,
+            area,
+            sums,
+            weights,
+            alphas,
+            squares,
+            samples,
+        })
+    }
+}
+
+//Render the samples of the slice
+//The random numbers only depend on the seed, the pixel and the index of the sample, not on the threads
+//The stratified sampler also spreads them over the samples of the slice, from 0 to first_sample + samples
+//All the slices of an image should use the same sampler and filter, and the same source (see scene_hash())
+pub fn render_slice(camera : &Camera, world : &World, source : &[u8], threads : usize, sampler : SamplerKind, filter : Filter, slice : &Slice) -> Result<Partial, PartialError> {
+    if slice.x as u64 + slice.width as u64 > camera.image_width as u64 || slice.y as u64 + slice.height as u64 > camera.image_height as u64 {
+        return Err(PartialError::Invalid(format!("The slice goes out of the {}x{} image", camera.image_width, camera.image_height)));
+    }
+
+    let framebuffer = Mutex::new(Framebuffer::new(camera.image_width, camera.image_height));
+    let pass = Pass {
+        camera,
+        world,
+        threads,
+        region : slice.region(),
+        samples : slice.samples,
+        target : slice.samples,
+        first_sample : slice.first_sample,
+        sampler,
+        filter,
+        seed : slice.seed,
+        mask : None,
+        aovs : None,
+        log_tiles : true,
+    };
+    pass.run(&framebuffer, &|| false);
+    let framebuffer = framebuffer.into_inner().expect("A render thread panicked");
+
+    //Only the pixels the samples of the slice reach are kept
+    let area = slice.region().expand(filter.margin(), camera.image_width, camera.image_height);
+    let pixels = area.width as usize*area.height as usize;
+    let (mut sums, mut weights, mut alphas, mut squares, mut samples) = (Vec::with_capacity(pixels), Vec::with_capacity(pixels), Vec::with_capacity(pixels), Vec::with_capacity(pixels), Vec::with_capacity(pixels));
+    for j in area.y..area.y + area.height {
+        let row = (j*camera.image_width + area.x) as usize..(j*camera.image_width + area.x + area.width) as usize;
+        sums.extend_from_slice(&framebuffer.sums[row.clone()]);
+        weights.extend_from_slice(&framebuffer.weights[row.clone()]);
+        alphas.extend_from_slice(&framebuffer.alphas[row.clone()]);
+        squares.extend_from_slice(&framebuffer.squares[row.clone()]);
+        samples.extend_from_slice(&framebuffer.samples[row]);
+    }
+
+    Ok(Partial {
+        scene_hash : scene_hash(source, camera.image_width, camera.image_height, sampler, filter),
+        image_width : camera.image_width,
+        image_height : camera.image_height,
+        slice : *slice,
+        sampler,
+        working_space : world.working_space,
+        area,
+        sums,
+        weights,
+        alphas,
+        squares,
+        samples,
+    })
+}
+
+//Add the samples of the partial renders of an image, with the working space they were rendered in
+//Pixels no slice covers are left without samples
+pub fn merge(partials : &[Partial]) -> Result<(Framebuffer, WorkingSpace), PartialError> {
+    let Some(first) = partials.first() else {
+        return Err(PartialError::Invalid(String::from("Nothing to merge")));
+    };
+    let mut framebuffer = Framebuffer::new(first.image_width, first.image_height);
+
+    for (index, partial) in partials.iter().enumerate() {
+        if partial.scene_hash != first.scene_hash {
+            return Err(PartialError::Mismatch(format!("Partial {index} was rendered from another scene than partial 0")));
+        }
+        if (partial.image_width, partial.image_height) != (first.image_width, first.image_height) {
+            return Err(PartialError::Mismatch(format!("Partial {index} is {}x{} instead of {}x{}", partial.image_width, partial.image_height, first.image_width, first.image_height)));
+        }
+        if partial.working_space != first.working_space {
+            return Err(PartialError::Mismatch(format!("Partial {index} is in {:?} instead of {:?}", partial.working_space, first.working_space)));
+        }
+        if let Some(other) = partials[..index].iter().position(|other| other.slice.overlaps(&partial.slice)) {
+            return Err(PartialError::Overlap(format!("Partials {other} and {index} have the same seed and share samples of some pixels")));
+        }
+
+        framebuffer.add_tile(&partial.area, &partial.sums, &partial.weights, &partial.alphas, &partial.squares, &partial.samples);
+    }
+    Ok((framebuffer, first.working_space))
+}
+
+//Load and merge the files written by Partial::save()
+pub fn merge_files<P : AsRef<Path>>(paths : &[P]) -> Result<(Framebuffer, WorkingSpace), PartialError> {
+    let partials = paths.iter().map(Partial::load).collect::<Result<Vec<Partial>, PartialError>>()?;
+    merge(&partials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{point::Point, material::{Texture, diffuse::Diffuse}};
+
+    #[test]
+    fn slices_are_deterministic_and_merge() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 50, 50./40., 60., 0.1, 3.);
+        let mut world = World::new();
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 1., Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 }))));
+
+        //Two hosts share the first samples of the image, a third one adds more samples to the left half
+        let top = render_slice(&camera, &world, b"scene", 1, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(0, 0, 50, 25, 0, 3, 7)).unwrap();
+        let again = render_slice(&camera, &world, b"scene", 4, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(0, 0, 50, 25, 0, 3, 7)).unwrap();
+        assert!(top.sums.iter().zip(&again.sums).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+
+        let bottom = render_slice(&camera, &world, b"scene", 2, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(0, 25, 50, 15, 0, 3, 7)).unwrap();
+        let left = render_slice(&camera, &world, b"scene", 2, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(0, 0, 25, 40, 3, 2, 7)).unwrap();
+
+        let path = std::env::temp_dir().join(format!("partial_test_{}.rtpt", std::process::id()));
+        left.save(&path).unwrap();
+        let left = Partial::load(&path).unwrap();
+        //A damaged width doesn't wrap around
+        let mut damaged = fs::read(&path).unwrap();
+        damaged[48..52].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &damaged).unwrap();
+        assert!(matches!(Partial::load(&path), Err(PartialError::Invalid(_))));
+        fs::remove_file(&path).unwrap();
+        //The file holds what is needed to render it again
+        let rendered_again = render_slice(&camera, &world, b"scene", 3, left.sampler, Filter::Box(0.5), &left.slice).unwrap();
+        assert!(left.sums.iter().zip(&rendered_again.sums).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+
+        let (framebuffer, working_space) = merge(&[top, bottom, left]).unwrap();
+        assert_eq!((framebuffer.samples(10, 35), framebuffer.samples(40, 35)), (5, 3));
+        assert_eq!(working_space, WorkingSpace::Rec709);
+
+        assert!(matches!(merge(&[again, render_slice(&camera, &world, b"scene", 1, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(20, 20, 5, 5, 2, 1, 7)).unwrap()]), Err(PartialError::Overlap(_))));
+        assert!(render_slice(&camera, &world, b"scene", 1, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(40, 0, 20, 5, 0, 1, 7)).is_err());
+
+        //The sums of different working spaces don't add up
+        let rec709 = render_slice(&camera, &world, b"scene", 1, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(0, 0, 5, 5, 0, 1, 8)).unwrap();
+        world.working_space = WorkingSpace::AcesCg;
+        let aces = render_slice(&camera, &world, b"scene", 1, SamplerKind::Sobol, Filter::Box(0.5), &Slice::new(0, 0, 5, 5, 1, 1, 8)).unwrap();
+        aces.save(&path).unwrap();
+        let aces = Partial::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(aces.working_space, WorkingSpace::AcesCg);
+        assert!(matches!(merge(&[rec709, aces]), Err(PartialError::Mismatch(_))));
+    }
+
+    #[test]
+    fn splatted_slices_match_the_whole_image() {
+        let camera = Camera::new(Point { x: 0., y: 0., z: 0. }, Point { x: 0., y: 0., z: -1. }, 40, 40./30., 60., 0., 3.);
+        let mut world = World::new();
+        world.add_sphere(Point { x: 0., y: 0., z: -3. }, 1., Arc::new(Texture::Diffuse(Diffuse::new(Color { r: 0.5, g: 0.5, b: 0.5 }))));
+        let filter = Filter::gaussian(1.5);
+
+        //The samples near the border of a slice are splatted on the pixels of the next one
+        let whole = crate::render::render(&camera, &world, 4, 2, SamplerKind::Sobol, filter, 3);
+        let top = render_slice(&camera, &world, b"scene", 2, SamplerKind::Sobol, filter, &Slice::new(0, 0, 40, 13, 0, 4, 3)).unwrap();
+        let bottom = render_slice(&camera, &world, b"scene", 2, SamplerKind::Sobol, filter, &Slice::new(0, 13, 40, 17, 0, 4, 3)).unwrap();
+        let (merged, _) = merge(&[top, bottom]).unwrap();
+        for (i, j) in [(20, 12), (20, 13), (5, 0), (39, 29)] {
+            let (a, b) = (whole.color(i, j), merged.color(i, j));
+            assert!((a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs() < 1e-9);
+        }
+        assert_eq!(merged.samples(20, 13), 4);
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{self, Write};
+
+const MAGIC : [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+//Single part image of scan lines
+const VERSION : u32 = 2;
+const FLOAT : i32 = 2;
+
+//Minimal OpenEXR writer : scan lines one by one without compression, 32 bits float channels
+//The values of each channel are given row by row from the top, the file stores the channels sorted by name
+pub fn write<W : Write>(mut out : W, width : u32, height : u32, channels : &[(&str, &[f32])]) -> io::Result<()> {
+    let mut channels = channels.to_vec();
+    channels.sort_by_key(|(name, _)| *name);
+    assert!(channels.iter().all(|(_, values)| values.len() == (width*height) as usize), "A channel doesn't have the size of the image");
+
+    let mut header = vec![];
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    let mut list = vec![];
+    for (name, _) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        //Type, linear flag and 3 reserved bytes, then the sampling in x and y
+        list.extend_from_slice(&FLOAT.to_le_bytes());
+        list.extend_from_slice(&[0; 4]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window : Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|value| value.to_le_bytes()).collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    //Increasing y
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    out.write_all(&header)?;
+
+    //Offsets of the lines in the file, then each line as its y, its size and the values of every channel
+    let line_size = channels.len()*width as usize*4;
+    let first = header.len() + height as usize*8;
+    for y in 0..height as usize {
+        out.write_all(&((first + y*(8 + line_size)) as u64).to_le_bytes())?;
+    }
+    for y in 0..height as usize {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+        for (_, values) in &channels {
+            for value in &values[y*width as usize..(y + 1)*width as usize] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn attribute(header : &mut Vec<u8>, name : &str, ty : &str, value : &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(ty.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
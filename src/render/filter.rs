@@ -0,0 +1,94 @@
+//Reconstruction filter : weight of a sample in the pixels around it, from the offset between the sample
+//and the center of the pixel in pixels
+//The filters are separable, the weight is the product of the weights of the two offsets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    //Same weight in the square of the radius, with 0.5 each sample only counts in its own pixel
+    Box(f64),
+    //Decreasing linearly to 0 at the radius
+    Tent(f64),
+    //Gaussian of falloff alpha, shifted to reach 0 at the radius
+    Gaussian { radius : f64, alpha : f64 },
+    //Cubic of Mitchell and Netravali, its negative lobes keep the image sharper
+    Mitchell { radius : f64, b : f64, c : f64 },
+    //Sinc windowed by a wider sinc, reaching 0 at the radius
+    Lanczos(f64),
+}
+
+impl Filter {
+    pub fn gaussian(radius : f64) -> Filter {
+        Filter::Gaussian { radius, alpha: 2. }
+    }
+
+    //With the parameters advised by Mitchell and Netravali
+    pub fn mitchell(radius : f64) -> Filter {
+        Filter::Mitchell { radius, b: 1./3., c: 1./3. }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box(radius) | Filter::Tent(radius) | Filter::Lanczos(radius) => radius,
+            Filter::Gaussian { radius, .. } | Filter::Mitchell { radius, .. } => radius,
+        }
+    }
+
+    //Number of pixels on each side of its own pixel a sample can reach
+    pub fn margin(&self) -> u32 {
+        (self.radius() - 0.5).ceil().max(0.) as u32
+    }
+
+    pub fn weight(&self, x : f64, y : f64) -> f64 {
+        self.weight_1d(x)*self.weight_1d(y)
+    }
+
+    fn weight_1d(&self, x : f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.;
+        }
+        match *self {
+            Filter::Box(_) => 1.,
+            Filter::Tent(radius) => radius - x,
+            Filter::Gaussian { radius, alpha } => ((-alpha*x*x).exp() - (-alpha*radius*radius).exp()).max(0.),
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.*x/radius;
+                if x > 1. {
+                    ((-b - 6.*c)*x*x*x + (6.*b + 30.*c)*x*x + (-12.*b - 48.*c)*x + 8.*b + 24.*c)/6.
+                } else {
+                    ((12. - 9.*b - 6.*c)*x*x*x + (-18. + 12.*b + 6.*c)*x*x + 6. - 2.*b)/6.
+                }
+            },
+            Filter::Lanczos(radius) => sinc(x)*sinc(x/radius),
+        }
+    }
+}
+
+fn sinc(x : f64) -> f64 {
+    if x < 1e-5 {
+        return 1.;
+    }
+    let x = std::f64::consts::PI*x;
+    x.sin()/x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights() {
+        assert_eq!((Filter::Box(0.5).weight(0.4, -0.5), Filter::Box(0.5).weight(0.6, 0.)), (1., 0.));
+        assert_eq!((Filter::Box(0.5).margin(), Filter::Tent(1.).margin(), Filter::gaussian(1.5).margin(), Filter::Lanczos(3.).margin()), (0, 1, 1, 3));
+        assert!((Filter::Tent(2.).weight(1., 0.5) - 1.5).abs() < 1e-12);
+
+        //Mitchell is 8/9 at the center, 1/18 at half the radius and 0 at the radius
+        let mitchell = Filter::mitchell(2.);
+        assert!((mitchell.weight(0., 0.) - 64./81.).abs() < 1e-12);
+        assert!((mitchell.weight(1., 0.) - 8./9./18.).abs() < 1e-12);
+        assert!(mitchell.weight(2., 0.).abs() < 1e-12);
+        assert!(Filter::mitchell(2.).weight(1.5, 0.) < 0.);
+
+        assert!(Filter::Lanczos(3.).weight(1., 0.).abs() < 1e-12);
+        assert!((Filter::gaussian(1.5).weight(1.5, 0.)).abs() < 1e-12);
+    }
+}
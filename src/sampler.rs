@@ -0,0 +1,293 @@
+use crate::random::mix;
+
+//Dimensions used by the camera : the position in the pixel, on the lens then the time
+pub const CAMERA_DIMENSIONS : u32 = 5;
+//Dimensions a material can use at each bounce
+pub const VERTEX_DIMENSIONS : u32 = 6;
+
+//Bases of the dimensions of the Halton sequence, the next ones are independent
+const PRIMES : [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+//Largest f64 below 1
+const ONE_MINUS_EPSILON : f64 = 1. - f64::EPSILON/2.;
+
+//How the numbers of the samples of a pixel are spread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    //Unrelated random numbers
+    Independent,
+    //Each sample in its own stratum of every dimension, at a random place in it
+    Stratified,
+    //Halton sequence (radical inverses in prime bases) shifted randomly in each pixel
+    Halton,
+    //Sobol (0,2)-sequence padded over the dimensions, with Owen scrambling
+    Sobol,
+}
+
+impl SamplerKind {
+    //Number of the kind in the files, it doesn't change with the order of the variants
+    pub fn code(self) -> u32 {
+        match self {
+            SamplerKind::Independent => 0,
+            SamplerKind::Stratified => 1,
+            SamplerKind::Halton => 2,
+            SamplerKind::Sobol => 3,
+        }
+    }
+
+    pub fn from_code(code : u32) -> Option<SamplerKind> {
+        [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol].into_iter().find(|kind| kind.code() == code)
+    }
+}
+
+//The numbers of one sample of a pixel
+//Every decision of the path has its own dimension, so the same decision of all the samples
+//of a pixel gets numbers spread well together
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    kind : SamplerKind,
+    seed : u64, //Seed of the pixel
+    sample : u32,
+    samples : u32, //Samples expected in the pixel, for the strata
+    dimension : u32,
+}
+
+impl Sampler {
+    pub fn new(kind : SamplerKind, seed : u64, i : u32, j : u32, sample : u32, samples : u32) -> Sampler {
+        Sampler {
+            kind,
+            seed : mix(&[seed, i as u64, j as u64]),
+            sample,
+            samples : samples.max(1),
+            dimension : 0,
+        }
+    }
+
+    //The numbers of a bounce start at the same dimension whatever the previous bounces took
+    pub fn start_vertex(&mut self, depth : u32) {
+        self.dimension = CAMERA_DIMENSIONS + depth*VERTEX_DIMENSIONS;
+    }
+
+    pub fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match self.kind {
+            SamplerKind::Independent => self.random(dimension),
+            SamplerKind::Stratified => {
+                let stratum = permute(self.sample % self.samples, self.samples, self.hash(dimension) as u32);
+                (stratum as f64 + self.random(dimension))/self.samples as f64
+            },
+            SamplerKind::Halton => self.halton(dimension),
+            SamplerKind::Sobol => {
+                let hash = self.hash(dimension);
+                let index = owen_scramble(self.sample, hash as u32);
+                to_unit(owen_scramble(index.reverse_bits(), (hash >> 32) as u32))
+            },
+        }
+    }
+
+    pub fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.dimension;
+        self.dimension += 2;
+        match self.kind {
+            SamplerKind::Independent => (self.random(dimension), self.random(dimension + 1)),
+            SamplerKind::Stratified => {
+                //Grid of at least samples cells, as square as possible
+                let columns = (self.samples as f64).sqrt().ceil() as u32;
+                let rows = self.samples.div_ceil(columns);
+                let cell = permute(self.sample % self.samples, columns*rows, self.hash(dimension) as u32);
+                (((cell%columns) as f64 + self.random(dimension))/columns as f64,
+                ((cell/columns) as f64 + self.random(dimension + 1))/rows as f64)
+            },
+            SamplerKind::Halton => (self.halton(dimension), self.halton(dimension + 1)),
+            SamplerKind::Sobol => {
+                let hash = self.hash(dimension);
+                let index = owen_scramble(self.sample, hash as u32);
+                (to_unit(owen_scramble(index.reverse_bits(), (hash >> 32) as u32)),
+                to_unit(owen_scramble(sobol_second(index), self.hash(dimension + 1) as u32)))
+            },
+        }
+    }
+
+    //Same for every sample of the pixel
+    fn hash(&self, dimension : u32) -> u64 {
+        mix(&[self.seed, dimension as u64])
+    }
+
+    //Different for every sample
+    fn random(&self, dimension : u32) -> f64 {
+        (mix(&[self.seed, dimension as u64, self.sample as u64]) >> 11) as f64/(1u64 << 53) as f64
+    }
+
+    fn halton(&self, dimension : u32) -> f64 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                //Cranley-Patterson rotation so the pixels don't share the same points
+                let shift = (self.hash(dimension) >> 11) as f64/(1u64 << 53) as f64;
+                let value = radical_inverse(self.sample, base) + shift;
+                (if value >= 1. { value - 1. } else { value }).min(ONE_MINUS_EPSILON)
+            },
+            None => self.random(dimension),
+        }
+    }
+}
+
+fn to_unit(value : u32) -> f64 {
+    value as f64/(1u64 << 32) as f64
+}
+
+//The digits of index in the base, mirrored around the decimal point
+fn radical_inverse(mut index : u32, base : u32) -> f64 {
+    let inverse_base = 1./base as f64;
+    let (mut inverse, mut factor) = (0., inverse_base);
+    while index > 0 {
+        inverse += (index%base) as f64*factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    inverse.min(ONE_MINUS_EPSILON)
+}
+
+//Second dimension of the Sobol sequence, the first one is the bits of the index reversed
+fn sobol_second(mut index : u32) -> u32 {
+    let mut direction = 1 << 31;
+    let mut value = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+//Nested uniform scrambling with the hash of Laine and Karras
+//A bit only changes with the higher ones, so the strata of the sequence are kept
+fn owen_scramble(value : u32, seed : u32) -> u32 {
+    let mut x = value.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+//Element index of a random permutation of 0..length chosen by the seed (Kensler)
+fn permute(mut index : u32, length : u32, seed : u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            return index.wrapping_add(seed)%length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{point::Point, ray::Ray, color::Color, world::Record};
+    use crate::material::{Texture, diffuse::Diffuse, metal::Metal, dielectric::Dielectric, pbr::Pbr, image::ImageTexture};
+
+    fn points(kind : SamplerKind, samples : u32, depth : u32) -> Vec<(f64, f64)> {
+        (0..samples).map(|sample| {
+            let mut sampler = Sampler::new(kind, 3, 10, 20, sample, samples);
+            sampler.get_1d();
+            sampler.start_vertex(depth);
+            sampler.get_2d()
+        }).collect()
+    }
+
+    #[test]
+    fn one_sample_per_stratum() {
+        //16 points of a bounce fill the 4x4 grid
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut cells = [0; 16];
+            for (x, y) in points(kind, 16, 2) {
+                assert!((0. ..1.).contains(&x) && (0. ..1.).contains(&y));
+                cells[(x*4.) as usize + 4*(y*4.) as usize] += 1;
+            }
+            assert_eq!(cells, [1; 16], "{kind:?}");
+        }
+
+        let mut strata = [0; 8];
+        for sample in 0..8 {
+            strata[(Sampler::new(SamplerKind::Stratified, 1, 0, 0, sample, 8).get_1d()*8.) as usize] += 1;
+        }
+        assert_eq!(strata, [1; 8]);
+    }
+
+    #[test]
+    fn dimensions_are_consistent() {
+        //The numbers of a bounce don't depend on what the previous ones took
+        for kind in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let mut sampler = Sampler::new(kind, 5, 1, 2, 7, 16);
+            sampler.get_2d();
+            sampler.start_vertex(3);
+            let first = sampler.get_1d();
+            sampler.get_2d();
+            sampler.start_vertex(3);
+            assert_eq!(sampler.get_1d(), first);
+        }
+        let mean = (0..1024).map(|sample| Sampler::new(SamplerKind::Halton, 0, 4, 4, sample, 1024).get_2d().1).sum::<f64>()/1024.;
+        assert!((mean - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn materials_stay_in_their_dimensions() {
+        let texture = Arc::new(ImageTexture::new(1, 1, vec![Color { r: 1., g: 1., b: 1. }]));
+        let white = Color { r: 1., g: 1., b: 1. };
+        let materials = [
+            Texture::Diffuse(Diffuse::new(white)),
+            Texture::Metal(Metal::new(white, 0.3)),
+            Texture::Dielectric(Dielectric::new(1.5)),
+            Texture::Pbr(Pbr::new(white, Some(texture), 0., 0.5)),
+            Texture::Pbr(Pbr::new(white, None, 0.5, 0.2)),
+            Texture::Pbr(Pbr::new(white, None, 1., 0.2)),
+        ];
+        let ray = || Ray::new(Point { x: 0., y: 0., z: 1. }, Point { x: 0.1, y: 0., z: -1. }, 0);
+        let hit = || Record::new(&ray(), 1., Point { x: 0.1, y: 0., z: 0. }, Point { x: 0., y: 0., z: 1. });
+        for material in &materials {
+            //Whatever the lobe taken, the next bounce starts where expected
+            for sample in 0..64 {
+                let mut sampler = Sampler::new(SamplerKind::Independent, 9, 0, 0, sample, 64);
+                sampler.start_vertex(0);
+                material.scatter(ray(), hit(), &mut sampler);
+                assert!(sampler.dimension <= CAMERA_DIMENSIONS + VERTEX_DIMENSIONS, "{material:?} took {} dimensions", sampler.dimension - CAMERA_DIMENSIONS);
+                //The PBR material always takes all of them, so its lobes don't share a dimension
+                if let Texture::Pbr(_) = material {
+                    assert_eq!(sampler.dimension, CAMERA_DIMENSIONS + VERTEX_DIMENSIONS);
+                }
+            }
+        }
+    }
+}
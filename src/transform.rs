@@ -0,0 +1,227 @@
+use std::ops::Mul;
+
+use crate::point::Point;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY : Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+//An affine transformation, stored as a 4x4 row major matrix along with its inverse
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    m : Matrix,
+    inv : Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { m: IDENTITY, inv: IDENTITY }
+    }
+
+    //Return None if the matrix can't be inverted
+    pub fn from_matrix(m : Matrix) -> Option<Transform> {
+        Some(Transform { m, inv: invert(&m)? })
+    }
+
+    //Same as from_matrix() but with the matrix given column by column (as in glTF)
+    pub fn from_columns(columns : Matrix) -> Option<Transform> {
+        Transform::from_matrix(transpose(&columns))
+    }
+
+    pub fn translate(offset : Point<f64>) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        (m[0][3], m[1][3], m[2][3]) = (offset.x, offset.y, offset.z);
+        (inv[0][3], inv[1][3], inv[2][3]) = (-offset.x, -offset.y, -offset.z);
+        Transform { m, inv }
+    }
+
+    //Panic if one of the factors is 0
+    pub fn scale(factors : Point<f64>) -> Transform {
+        assert!(factors.x != 0. && factors.y != 0. && factors.z != 0., "Scaling by 0 can't be inverted");
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        (m[0][0], m[1][1], m[2][2]) = (factors.x, factors.y, factors.z);
+        (inv[0][0], inv[1][1], inv[2][2]) = (1./factors.x, 1./factors.y, 1./factors.z);
+        Transform { m, inv }
+    }
+
+    //Rotation of angle (in degrees) around the axis, counterclockwise when the axis points toward the viewer
+    pub fn rotate(angle : f64, axis : Point<f64>) -> Transform {
+        let a = axis.unit();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let m = [
+            [a.x*a.x + (1. - a.x*a.x)*cos, a.x*a.y*(1. - cos) - a.z*sin, a.x*a.z*(1. - cos) + a.y*sin, 0.],
+            [a.x*a.y*(1. - cos) + a.z*sin, a.y*a.y + (1. - a.y*a.y)*cos, a.y*a.z*(1. - cos) - a.x*sin, 0.],
+            [a.x*a.z*(1. - cos) - a.y*sin, a.y*a.z*(1. - cos) + a.x*sin, a.z*a.z + (1. - a.z*a.z)*cos, 0.],
+            [0., 0., 0., 1.],
+        ];
+        //The inverse of a rotation is its transpose
+        Transform { m, inv: transpose(&m) }
+    }
+
+    pub fn matrix(&self) -> Matrix {
+        self.m
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { m: self.inv, inv: self.m }
+    }
+
+    pub fn point(&self, p : Point<f64>) -> Point<f64> {
+        apply(&self.m, p, 1.)
+    }
+
+    pub fn vector(&self, v : Point<f64>) -> Point<f64> {
+        apply(&self.m, v, 0.)
+    }
+
+    //Normals are transformed by the transpose of the inverse
+    pub fn normal(&self, n : Point<f64>) -> Point<f64> {
+        let inv = &self.inv;
+        Point {
+            x: inv[0][0]*n.x + inv[1][0]*n.y + inv[2][0]*n.z,
+            y: inv[0][1]*n.x + inv[1][1]*n.y + inv[2][1]*n.z,
+            z: inv[0][2]*n.x + inv[1][2]*n.y + inv[2][2]*n.z,
+        }
+    }
+
+    pub fn inverse_point(&self, p : Point<f64>) -> Point<f64> {
+        apply(&self.inv, p, 1.)
+    }
+
+    pub fn inverse_vector(&self, v : Point<f64>) -> Point<f64> {
+        apply(&self.inv, v, 0.)
+    }
+
+    //True if the transformation changes the handedness of the space
+    pub fn swaps_handedness(&self) -> bool {
+        let m = &self.m;
+        let det = m[0][0]*(m[1][1]*m[2][2] - m[1][2]*m[2][1])
+            - m[0][1]*(m[1][0]*m[2][2] - m[1][2]*m[2][0])
+            + m[0][2]*(m[1][0]*m[2][1] - m[1][1]*m[2][0]);
+        det < 0.
+    }
+}
+
+//Composition : (a*b) applies b first, then a
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs : Transform) -> Self::Output {
+        Transform {
+            m: product(&self.m, &rhs.m),
+            inv: product(&rhs.inv, &self.inv),
+        }
+    }
+}
+
+fn apply(m : &Matrix, p : Point<f64>, w : f64) -> Point<f64> {
+    let x = m[0][0]*p.x + m[0][1]*p.y + m[0][2]*p.z + m[0][3]*w;
+    let y = m[1][0]*p.x + m[1][1]*p.y + m[1][2]*p.z + m[1][3]*w;
+    let z = m[2][0]*p.x + m[2][1]*p.y + m[2][2]*p.z + m[2][3]*w;
+    if w == 0. {
+        return Point { x, y, z };
+    }
+    let hw = m[3][0]*p.x + m[3][1]*p.y + m[3][2]*p.z + m[3][3];
+    if hw == 1. {
+        Point { x, y, z }
+    }
+    else {
+        Point { x, y, z }/hw
+    }
+}
+
+fn product(a : &Matrix, b : &Matrix) -> Matrix {
+    let mut m = [[0.; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k]*b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(m : &Matrix) -> Matrix {
+    let mut t = [[0.; 4]; 4];
+    for (i, row) in t.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    t
+}
+
+//Gauss-Jordan elimination with partial pivoting
+fn invert(m : &Matrix) -> Option<Matrix> {
+    let mut a = *m;
+    let mut inv = IDENTITY;
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let scale = 1./a[col][col];
+        for k in 0..4 {
+            a[col][k] *= scale;
+            inv[col][k] *= scale;
+        }
+        for row in 0..4 {
+            if row != col {
+                let factor = a[row][col];
+                for k in 0..4 {
+                    a[row][k] -= factor*a[col][k];
+                    inv[row][k] -= factor*inv[col][k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a : Point<f64>, b : Point<f64>) -> bool {
+        (a - b).norm() < 1e-9
+    }
+
+    #[test]
+    fn compose_and_invert() {
+        let t = Transform::translate(Point { x: 1., y: 2., z: 3. })
+            * Transform::rotate(90., Point { x: 0., y: 1., z: 0. })
+            * Transform::scale(Point { x: 2., y: 2., z: 2. });
+        let p = Point { x: 1., y: 0., z: 0. };
+
+        //Scaled to (2,0,0), rotated to (0,0,-2), translated
+        assert!(close(t.point(p), Point { x: 1., y: 2., z: 1. }));
+        assert!(close(t.inverse_point(t.point(p)), p));
+
+        let general = Transform::from_matrix(t.matrix()).unwrap();
+        assert!(close(general.inverse_point(Point { x: 1., y: 2., z: 1. }), p));
+        assert!(Transform::from_matrix([[0.; 4]; 4]).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = Transform::scale(Point { x: 1., y: 4., z: 1. });
+        let tangent = t.vector(Point { x: 1., y: 1., z: 0. });
+        let normal = t.normal(Point { x: 1., y: -1., z: 0. });
+        assert!((tangent&normal).abs() < 1e-12);
+    }
+}
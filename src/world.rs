@@ -1,19 +1,30 @@
 pub mod sphere;
 pub mod aabb;
+pub mod sdf;
+pub mod triangle;
+pub mod heightfield;
+pub mod mesh;
+pub mod instance;
 
 use std::sync::Arc;
 
 use sphere::Sphere;
-use crate::{point::Point, material::{Texture, diffuse::{self, Diffuse}}, color::Color};
+use sdf::{Sdf, SdfNode};
+use heightfield::Heightfield;
+use mesh::Mesh;
+use instance::Instance;
+use crate::{point::Point, material::{Texture, diffuse::Diffuse}, color::{Color, display::WorkingSpace}, transform::Transform};
 use self::aabb::Aabb;
 
-use super::Ray;
+use crate::ray::Ray;
 
 pub struct Record {
     pub t : f64,
     pub p : Point<f64>,
     pub normal : Point<f64>,
     pub front_face : bool,
+    pub uv : (f64, f64), //Texture coordinates, (0, 0) when the surface has none
+    pub color : Color<f64>, //Vertex color multiplying the albedo of the material, white when the surface has none
 }
 
 impl Record {
@@ -25,6 +36,8 @@ impl Record {
             p,
             normal,
             front_face,
+            uv : (0., 0.),
+            color : Color { r: 1., g: 1., b: 1. },
         }
     }
     
@@ -33,26 +46,91 @@ impl Record {
     }
 }
 
+//What Hitable::hit found, given back to get_records so the surface doesn't have to search it again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub t : f64,
+    pub primitive : usize, //Part of the surface hit, the triangle of a mesh
+    pub coords : (f64, f64), //Where on that part, the barycentric coordinates on a triangle
+}
+
+impl Hit {
+    pub fn new(t : f64) -> Hit {
+        Hit { t, primitive : 0, coords : (0., 0.) }
+    }
+}
+
 pub trait Hitable {
-    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<f64>; 
-    fn get_records(&self, r : &Ray, t : f64) -> Record;
+    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<Hit>; 
+    fn get_records(&self, r : &Ray, hit : &Hit) -> Record;
     fn get_bb(&self) -> (Point<f64>, Point<f64>);
 }
 
+#[derive(Debug)]
 pub enum Surface {
     Sphere(Sphere),
+    Sdf(Sdf),
+    Heightfield(Heightfield),
+    Mesh(Mesh),
+    Instance(Instance),
     AABB(Aabb),
 }
 
+impl Surface {
+    //None for the AABB, which hold their own surfaces
+    pub fn as_hitable(&self) -> Option<&dyn Hitable> {
+        match self {
+            Surface::Sphere(sphere) => Some(sphere),
+            Surface::Sdf(sdf) => Some(sdf),
+            Surface::Heightfield(field) => Some(field),
+            Surface::Mesh(mesh) => Some(mesh),
+            Surface::Instance(instance) => Some(instance),
+            Surface::AABB(_) => None,
+        }
+    }
+}
+
+//What a ray leaving the scene sees
+#[derive(Debug)]
+pub enum Background {
+    Sky, //Gradient from white at the horizon to blue at the zenith
+    Uniform(Color<f64>), //Constant radiance from every direction (pbrt infinite light)
+}
+
+impl Background {
+    pub fn color(&self, dir : &Point<f64>) -> Color<f64> {
+        match self {
+            Background::Sky => {
+                let a = (dir.unit().y + 1.0)*0.5;
+                Color {r: 1.0, g: 1.0, b: 1.0}*(1.0-a) + a*Color { r: 0.5, g: 0.7, b: 1.0 }
+            },
+            Background::Uniform(color) => *color,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct World {
     pub default_texture : Arc<Texture>,
     pub objects : Vec<(Surface, Arc<Texture>)>,
+    pub background : Background,
+    pub background_visible : bool, //The camera sees the background, else it only lights the scene and the image is transparent there
+    pub working_space : WorkingSpace, //The colors of the scene are given in it
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
 impl World {
     pub fn new() -> World {
         World {
             objects : Vec::new(),
+            background : Background::Sky,
+            background_visible : true,
+            working_space : WorkingSpace::Rec709,
             default_texture : Arc::new(Texture::Diffuse(Diffuse::new(Color::<f64> {r:1.0, g:1.0, b:1.0}))),
         }
     }
@@ -60,6 +138,9 @@ impl World {
     pub fn new_from_vec(objects : Vec<(Surface, Arc<Texture>)>) -> World {
         World {
             objects,
+            background : Background::Sky,
+            background_visible : true,
+            working_space : WorkingSpace::Rec709,
             default_texture : Arc::new(Texture::Diffuse(Diffuse::new(Color::<f64> {r:1.0, g:1.0, b:1.0}))),
         }
     }
@@ -70,19 +151,52 @@ impl World {
 
     pub fn add_sphere_without_collision(&mut self, center : Point<f64>, radius : f64, texture : Arc<Texture>) -> bool {
         for (object, _) in &self.objects {
-            match object {
-                Surface::Sphere(sphere) => {
-                    if (center-sphere.get_center()).norm() < (radius+sphere.get_radius()) {
-                        return false;
-                    }
-                },
-                _ => ()
+            if let Surface::Sphere(sphere) = object {
+                if (center-sphere.get_center()).norm() < (radius+sphere.get_radius()) {
+                    return false;
+                }
             }
         }
 
         self.add_sphere(center, radius, texture);
 
-        return true;
+        true
+    }
+
+    //Add a distance field surface, sphere traced inside the box [min, max]
+    pub fn add_sdf(&mut self, root : SdfNode, min : Point<f64>, max : Point<f64>, step_scale : f64, texture : Arc<Texture>) {
+        self.objects.push((Surface::Sdf(Sdf::new(root, min, max, step_scale)), texture));
+    }
+
+    pub fn add_heightfield(&mut self, heightfield : Heightfield, texture : Arc<Texture>) {
+        self.objects.push((Surface::Heightfield(heightfield), texture));
+    }
+
+    pub fn add_mesh(&mut self, mesh : Mesh, texture : Arc<Texture>) {
+        self.objects.push((Surface::Mesh(mesh), texture));
+    }
+
+    //Place a shared surface in the world with a transformation
+    pub fn add_instance(&mut self, surface : Arc<Surface>, transform : Transform, texture : Arc<Texture>) {
+        self.objects.push((Surface::Instance(Instance::new(surface, transform)), texture));
+    }
+
+    //The instance moves of velocity each second from its transform, blurred by the shutter of the camera
+    //Its bounding box holds its path up to the shutter given, the longest of the cameras rendering the world
+    pub fn add_moving_instance(&mut self, surface : Arc<Surface>, transform : Transform, velocity : Point<f64>, shutter : f64, texture : Arc<Texture>) {
+        self.objects.push((Surface::Instance(Instance::moving(surface, transform, velocity, shutter)), texture));
+    }
+
+    //Every surface with its texture, those of the AABB included, depth first
+    pub fn leaves(&self) -> Vec<(&Surface, &Arc<Texture>)> {
+        let mut leaves = vec![];
+        for (surface, texture) in &self.objects {
+            match surface {
+                Surface::AABB(aabb) => leaves.extend(aabb.subworld().leaves()),
+                _ => leaves.push((surface, texture)),
+            }
+        }
+        leaves
     }
 }
 
@@ -1,35 +1,32 @@
 use std::{sync::Arc, mem::swap};
 
-use crate::{point::Point, material::Texture};
+use crate::{point::Point, material::Texture, ray::Ray};
 
-use super::{World, Surface, Hitable};
+use super::{World, Surface, Hit};
 
 
 
+#[derive(Debug)]
 pub struct Aabb {
     min : Point<f64>,
     max : Point<f64>,
     subworld : World
 }
 
-impl Aabb {
-    pub fn new_one(surface : Surface, texture : Arc<Texture>) -> Aabb {
-        let (min, max) = match &surface {
-            Surface::Sphere(sphere) => sphere.get_bb(),
-            Surface::AABB(_) => panic!("Should not encapsulate AABB in AABB"),
-        };
-        Aabb {
-            min,
-            max,
-            subworld: World::new_from_vec(vec![(surface, texture)]),
-        }
-    }
+//Clip the ray against the box [min, max] (slab method)
+//Return the parametric interval of the ray inside the box, if any
+pub fn slab(min : Point<f64>, max : Point<f64>, r : &Ray, mut t_min : f64, mut t_max : f64) -> Option<(f64, f64)> {
+    let axis = [
+        (min.x, max.x, r.orig().x, r.dir().x),
+        (min.y, max.y, r.orig().y, r.dir().y),
+        (min.z, max.z, r.orig().z, r.dir().z),
+    ];
+
+    for (min, max, orig, dir) in axis {
+        let inv_d = 1.0/dir;
+        let mut t0 = (min - orig) * inv_d;
+        let mut t1 = (max - orig) * inv_d;
 
-    pub fn hit<'a, 'b>(&'a self, r : &'b crate::ray::Ray, mut t_min : f64, mut t_max : f64) -> Option<(f64, &'a Surface, Arc<Texture>)> {
-        let inv_d = 1.0/r.dir().x;
-        let mut t0 = (self.min.x - r.orig().x) * inv_d;
-        let mut t1 = (self.max.x - r.orig().x) * inv_d;
-    
         if inv_d<0.0 {
             swap(&mut t0, &mut t1);
         }
@@ -39,35 +36,31 @@ impl Aabb {
         if t_max < t_min {
             return None;
         }
+    }
 
-        let inv_d = 1.0/r.dir().y;
-        let mut t0 = (self.min.y - r.orig().y) * inv_d;
-        let mut t1 = (self.max.y - r.orig().y) * inv_d;
-    
-        if inv_d<0.0 {
-            swap(&mut t0, &mut t1);
-        }
-        t_min = t0.max(t_min);
-        t_max = t1.min(t_max);
+    Some((t_min, t_max))
+}
 
-        if t_max < t_min {
-            return None;
-        }
+impl Aabb {
+    pub fn subworld(&self) -> &World {
+        &self.subworld
+    }
 
-        let inv_d = 1.0/r.dir().z;
-        let mut t0 = (self.min.z - r.orig().z) * inv_d;
-        let mut t1: f64 = (self.max.z - r.orig().z) * inv_d;
-    
-        if inv_d<0.0 {
-            swap(&mut t0, &mut t1);
+    pub fn new_one(surface : Surface, texture : Arc<Texture>) -> Aabb {
+        let (min, max) = match surface.as_hitable() {
+            Some(hitable) => hitable.get_bb(),
+            None => panic!("Should not encapsulate AABB in AABB"),
+        };
+        Aabb {
+            min,
+            max,
+            subworld: World::new_from_vec(vec![(surface, texture)]),
         }
-        t_min = t0.max(t_min);
-        t_max = t1.min(t_max);
+    }
 
-        if t_max < t_min {
-            return None;
-        }
+    pub fn hit<'a>(&'a self, r : &Ray, t_min : f64, t_max : f64) -> Option<(Hit, &'a Surface, Arc<Texture>)> {
+        slab(self.min, self.max, r, t_min, t_max)?;
 
-        return r.object_hitted(&self.subworld);
+        r.object_hitted(&self.subworld)
     }
-}
\ No newline at end of file
+}
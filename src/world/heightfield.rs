@@ -0,0 +1,331 @@
+use std::{fs, io, path::Path};
+
+use crate::{point::Point, ray::Ray};
+use super::{Hit, Hitable, Record, aabb::slab, triangle};
+
+//A regular grid of elevations scaled into the box [min, max]
+//The columns of the grid go along x, the rows along z and the elevation along y
+//Each cell is made of two triangles, found by walking the cells crossed by the ray (grid DDA)
+#[derive(Debug)]
+pub struct Heightfield {
+    cols : usize,
+    rows : usize,
+    heights : Vec<f64>, //Normalized between 0 and 1
+    min : Point<f64>,
+    max : Point<f64>,
+}
+
+impl Heightfield {
+    //Heights must be given row by row and lie between 0 (min.y) and 1 (max.y)
+    pub fn new(cols : usize, rows : usize, heights : Vec<f64>, min : Point<f64>, max : Point<f64>) -> Heightfield {
+        assert!(cols >= 2 && rows >= 2, "A heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), cols*rows, "The number of heights doesn't match the size of the grid");
+        Heightfield {
+            cols,
+            rows,
+            heights,
+            min,
+            max,
+        }
+    }
+
+    //Load a grayscale PGM image (P2 or P5, 8 or 16 bits), black is min.y and white max.y
+    pub fn from_pgm<P : AsRef<Path>>(path : P, min : Point<f64>, max : Point<f64>) -> io::Result<Heightfield> {
+        let (cols, rows, heights) = parse_pgm(&fs::read(path)?)?;
+        Ok(Heightfield::new(cols, rows, heights, min, max))
+    }
+
+    //Load a raw grid of little endian f32, the lowest value is min.y and the highest max.y
+    pub fn from_raw_f32<P : AsRef<Path>>(path : P, cols : usize, rows : usize, min : Point<f64>, max : Point<f64>) -> io::Result<Heightfield> {
+        let heights = parse_raw_f32(&fs::read(path)?, cols, rows)?;
+        Ok(Heightfield::new(cols, rows, heights, min, max))
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        ((self.max.x - self.min.x)/(self.cols - 1) as f64,
+        (self.max.z - self.min.z)/(self.rows - 1) as f64)
+    }
+
+    fn height(&self, i : usize, j : usize) -> f64 {
+        self.min.y + self.heights[j*self.cols + i]*(self.max.y - self.min.y)
+    }
+
+    fn vertex(&self, i : usize, j : usize) -> Point<f64> {
+        let (dx, dz) = self.cell_size();
+        Point { x: self.min.x + i as f64*dx, y: self.height(i, j), z: self.min.z + j as f64*dz }
+    }
+
+    //Normal of a grid vertex estimated from its neighbours
+    fn vertex_normal(&self, i : usize, j : usize) -> Point<f64> {
+        let (dx, dz) = self.cell_size();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.cols - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let slope_x = (self.height(i1, j) - self.height(i0, j))/((i1 - i0) as f64*dx);
+        let slope_z = (self.height(i, j1) - self.height(i, j0))/((j1 - j0) as f64*dz);
+        Point { x: -slope_x, y: 1., z: -slope_z }.unit()
+    }
+
+    //Test the two triangles of the cell (i, j)
+    fn hit_cell(&self, r : &Ray, i : usize, j : usize, t_min : f64, t_max : f64) -> Option<f64> {
+        let v00 = self.vertex(i, j);
+        let v10 = self.vertex(i + 1, j);
+        let v01 = self.vertex(i, j + 1);
+        let v11 = self.vertex(i + 1, j + 1);
+
+        let first = triangle::intersect(r, v00, v10, v11, t_min, t_max).map(|(t, _, _)| t);
+        let t_max = first.unwrap_or(t_max);
+        triangle::intersect(r, v00, v11, v01, t_min, t_max).map(|(t, _, _)| t).or(first)
+    }
+}
+
+impl Hitable for Heightfield {
+    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<Hit> {
+        let (t0, t1) = slab(self.min, self.max, r, t_min, t_max)?;
+        let (dx, dz) = self.cell_size();
+        let (ncx, ncz) = (self.cols - 1, self.rows - 1);
+        let dir = *(r.dir());
+
+        //Cell containing the entry point
+        let entry = r.at(t0);
+        let to_cell = |v : f64, size : f64, n : usize| (((v/size).floor().max(0.)) as usize).min(n - 1);
+        let mut i = to_cell(entry.x - self.min.x, dx, ncx);
+        let mut j = to_cell(entry.z - self.min.z, dz, ncz);
+
+        //Parameters of the DDA along x and z
+        let step_i : isize = if dir.x > 0. { 1 } else { -1 };
+        let step_j : isize = if dir.z > 0. { 1 } else { -1 };
+        let next_boundary = |cell : usize, step : isize, size : f64, min : f64, orig : f64, dir : f64| {
+            if dir == 0. {
+                return f64::INFINITY;
+            }
+            let boundary = min + (cell as f64 + if step > 0 { 1. } else { 0. })*size;
+            (boundary - orig)/dir
+        };
+        let mut t_next_x = next_boundary(i, step_i, dx, self.min.x, r.orig().x, dir.x);
+        let mut t_next_z = next_boundary(j, step_j, dz, self.min.z, r.orig().z, dir.z);
+        let t_delta_x = if dir.x == 0. { f64::INFINITY } else { dx/dir.x.abs() };
+        let t_delta_z = if dir.z == 0. { f64::INFINITY } else { dz/dir.z.abs() };
+
+        let mut t_enter = t0;
+        loop {
+            let t_exit = t_next_x.min(t_next_z).min(t1);
+
+            //Skip the cell if the ray stays above or below all its vertices
+            let (h00, h10, h01, h11) = (self.height(i, j), self.height(i + 1, j), self.height(i, j + 1), self.height(i + 1, j + 1));
+            let (y_enter, y_exit) = (r.orig().y + dir.y*t_enter, r.orig().y + dir.y*t_exit);
+            let cell_min = h00.min(h10).min(h01).min(h11);
+            let cell_max = h00.max(h10).max(h01).max(h11);
+            if y_enter.min(y_exit) <= cell_max && y_enter.max(y_exit) >= cell_min {
+                if let Some(t) = self.hit_cell(r, i, j, t_min, t_max) {
+                    return Some(Hit::new(t));
+                }
+            }
+
+            if t_exit >= t1 {
+                return None;
+            }
+
+            //Go to the next cell
+            if t_next_x < t_next_z {
+                let next = i as isize + step_i;
+                if next < 0 || next >= ncx as isize {
+                    return None;
+                }
+                i = next as usize;
+                t_next_x += t_delta_x;
+            }
+            else {
+                let next = j as isize + step_j;
+                if next < 0 || next >= ncz as isize {
+                    return None;
+                }
+                j = next as usize;
+                t_next_z += t_delta_z;
+            }
+            t_enter = t_exit;
+        }
+    }
+
+    fn get_records(&self, r : &Ray, hit : &Hit) -> Record {
+        let t = hit.t;
+        let p = r.at(t);
+        let (dx, dz) = self.cell_size();
+        let fx = ((p.x - self.min.x)/dx).clamp(0., (self.cols - 1) as f64);
+        let fz = ((p.z - self.min.z)/dz).clamp(0., (self.rows - 1) as f64);
+        let i = (fx.floor() as usize).min(self.cols - 2);
+        let j = (fz.floor() as usize).min(self.rows - 2);
+        let (u, v) = (fx - i as f64, fz - j as f64);
+
+        //Interpolate the vertex normals with the barycentric coordinates of the triangle hit
+        let n00 = self.vertex_normal(i, j);
+        let n11 = self.vertex_normal(i + 1, j + 1);
+        let normal = if u >= v {
+            n00*(1. - u) + self.vertex_normal(i + 1, j)*(u - v) + n11*v
+        }
+        else {
+            n00*(1. - v) + self.vertex_normal(i, j + 1)*(v - u) + n11*u
+        };
+
+        Record::new(r, t, p, normal.unit())
+    }
+
+    fn get_bb(&self) -> (Point<f64>, Point<f64>) {
+        (self.min, self.max)
+    }
+}
+
+fn invalid(msg : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+//Parse a binary (P5) or ascii (P2) PGM image into normalized heights
+fn parse_pgm(data : &[u8]) -> io::Result<(usize, usize, Vec<f64>)> {
+    let mut pos = 0;
+    //Read the next whitespace separated token of the header, skipping comments
+    let mut token = || -> io::Result<String> {
+        loop {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < data.len() && data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("Truncated PGM header"));
+        }
+        Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+    };
+
+    let magic = token()?;
+    let number = |s : String| s.parse::<usize>().map_err(|_| invalid("Invalid number in PGM header"));
+    let cols = number(token()?)?;
+    let rows = number(token()?)?;
+    let maxval = number(token()?)?;
+    if cols < 2 || rows < 2 || maxval == 0 || maxval > 65535 {
+        return Err(invalid("Invalid PGM dimensions or maximum value"));
+    }
+    let count = cols.checked_mul(rows).ok_or_else(|| invalid("Invalid PGM dimensions or maximum value"))?;
+
+    let values : Vec<usize> = match magic.as_str() {
+        "P2" => {
+            //The header alone doesn't size the memory, each value takes at least a byte of the file
+            let mut values = Vec::with_capacity(count.min(data.len()));
+            for _ in 0..count {
+                values.push(number(token()?)?);
+            }
+            values
+        },
+        "P5" => {
+            //A single whitespace separates the header from the raster
+            let raster = &data[(pos + 1).min(data.len())..];
+            let bytes = if maxval < 256 { 1 } else { 2 };
+            if count.checked_mul(bytes).is_none_or(|size| raster.len() < size) {
+                return Err(invalid("Truncated PGM raster"));
+            }
+            if bytes == 1 {
+                raster[..count].iter().map(|&b| b as usize).collect()
+            }
+            else {
+                raster[..count*2].chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).collect()
+            }
+        },
+        _ => return Err(invalid("Not a PGM image (expected P2 or P5)")),
+    };
+
+    Ok((cols, rows, values.into_iter().map(|v| (v.min(maxval) as f64)/(maxval as f64)).collect()))
+}
+
+//Parse a raw grid of little endian f32 and normalize it between 0 and 1
+fn parse_raw_f32(data : &[u8], cols : usize, rows : usize) -> io::Result<Vec<f64>> {
+    if cols < 2 || rows < 2 {
+        return Err(invalid("A raw grid needs at least 2 columns and 2 rows"));
+    }
+    if cols.checked_mul(rows).and_then(|count| count.checked_mul(4)) != Some(data.len()) {
+        return Err(invalid("The size of the raw grid doesn't match its dimensions"));
+    }
+    let values : Vec<f64> = data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64).collect();
+    if values.iter().any(|v| !v.is_finite()) {
+        return Err(invalid("The raw grid contains non finite values"));
+    }
+
+    let lowest = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let highest = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = if highest > lowest { highest - lowest } else { 1. };
+    Ok(values.into_iter().map(|v| (v - lowest)/range).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> Heightfield {
+        //Elevation growing with x, from 0 to 1 over 3 units
+        let heights = (0..16).map(|k| (k%4) as f64/3.).collect();
+        Heightfield::new(4, 4, heights, Point { x: 0., y: 0., z: 0. }, Point { x: 3., y: 1., z: 3. })
+    }
+
+    #[test]
+    fn vertical_ray_hits_ramp() {
+        let field = ramp();
+        for (x, z) in [(0.2, 0.3), (1.5, 2.7), (2.9, 0.1), (2.2, 2.2)] {
+            let ray = Ray::new(Point { x, y: 5., z }, Point { x: 0., y: -1., z: 0. }, 0);
+            let hit = field.hit(&ray, 0.001, f64::MAX).expect("Should hit the ramp");
+            assert!((ray.at(hit.t).y - x/3.).abs() < 1e-9);
+
+            let normal = field.get_records(&ray, &hit).normal;
+            assert!((normal - Point { x: -1., y: 3., z: 0. }.unit()).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn grazing_ray_walks_cells() {
+        let field = ramp();
+        //Ray going toward +x just above the ground, hitting the slope at y = 0.5
+        let ray = Ray::new(Point { x: -1., y: 0.5, z: 1.3 }, Point { x: 1., y: 0., z: 0.1 }, 0);
+        let hit = field.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((ray.at(hit.t).x - 1.5).abs() < 1e-9);
+
+        let above = Ray::new(Point { x: -1., y: 1.5, z: 1.3 }, Point { x: 1., y: 0., z: 0.1 }, 0);
+        assert!(field.hit(&above, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn parse_pgm_files() {
+        let ascii = b"P2\n# comment\n2 2\n255\n0 255\n51 102\n";
+        let (cols, rows, heights) = parse_pgm(ascii).unwrap();
+        assert_eq!((cols, rows), (2, 2));
+        assert_eq!(heights, vec![0., 1., 0.2, 0.4]);
+
+        let mut binary = b"P5 2 2 65535\n".to_vec();
+        binary.extend_from_slice(&[0, 0, 255, 255, 0, 0, 255, 255]);
+        let (_, _, heights) = parse_pgm(&binary).unwrap();
+        assert_eq!(heights, vec![0., 1., 0., 1.]);
+
+        assert!(parse_pgm(&binary[..binary.len() - 1]).is_err());
+        assert!(parse_pgm(b"P6 2 2 255\n").is_err());
+        assert!(parse_pgm(b"P5 4294967296 4294967296 255\n\0\0\0\0").is_err());
+        assert!(parse_pgm(b"P5 3074457345618258603 3 65535\n\0\0\0\0").is_err());
+        assert!(parse_pgm(b"P2 100000 100000 255\n0 1 2").is_err());
+    }
+
+    #[test]
+    fn parse_raw_files() {
+        let data : Vec<u8> = [1f32, 3., 2., 5.].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(parse_raw_f32(&data, 2, 2).unwrap(), vec![0., 0.5, 0.25, 1.]);
+
+        //Too small or not matching the size, an error and not a panic in Heightfield::new
+        assert_eq!(parse_raw_f32(&data[..4], 1, 1).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(parse_raw_f32(&data, 4, 1).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(parse_raw_f32(&data, 3, 2).is_err());
+        assert!(parse_raw_f32(&data, usize::MAX, 2).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use crate::{point::Point, ray::Ray, transform::Transform};
+use super::{Hit, Hitable, Record, Surface};
+
+//A surface placed in the world by a transformation, the surface itself can be shared between instances
+#[derive(Debug)]
+pub struct Instance {
+    surface : Arc<Surface>,
+    transform : Transform, //Object to world
+    velocity : Point<f64>, //Translation per second, the transform is the position at time 0
+    shutter : f64, //The rays come while the shutter is open, from time 0 to this one
+}
+
+impl Instance {
+    pub fn new(surface : Arc<Surface>, transform : Transform) -> Instance {
+        assert!(surface.as_hitable().is_some() && !matches!(surface.as_ref(), Surface::Instance(_)),
+            "Only primitive surfaces can be instanced");
+        Instance {
+            surface,
+            transform,
+            velocity : Point { x: 0., y: 0., z: 0. },
+            shutter : 0.,
+        }
+    }
+
+    //Moving in a straight line during the exposure, for the motion blur
+    //The shutter is the longest exposure of the cameras looking at it, its path is bounded up to then
+    pub fn moving(surface : Arc<Surface>, transform : Transform, velocity : Point<f64>, shutter : f64) -> Instance {
+        assert!(shutter >= 0., "The shutter can't close before it opens");
+        Instance { velocity, shutter, ..Instance::new(surface, transform) }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    //The direction is not normalized so the t of the local ray is the same as the world one
+    fn local_ray(&self, r : &Ray) -> Ray {
+        let orig = *r.orig() - self.velocity*r.time();
+        Ray::new(self.transform.inverse_point(orig), self.transform.inverse_vector(*r.dir()), r.get_depth()).at_time(r.time())
+    }
+
+    fn local(&self) -> &dyn Hitable {
+        self.surface.as_hitable().expect("Checked in Instance::new()")
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<Hit> {
+        self.local().hit(&self.local_ray(r), t_min, t_max)
+    }
+
+    fn get_records(&self, r : &Ray, hit : &Hit) -> Record {
+        let local = self.local().get_records(&self.local_ray(r), hit);
+        //Go back to the outward normal before transforming it
+        let local_normal = if local.front_face { local.normal } else { local.normal*(-1f64) };
+
+        let mut record = Record::new(r, hit.t, r.at(hit.t), self.transform.normal(local_normal).unit());
+        record.uv = local.uv;
+        record.color = local.color;
+        record
+    }
+
+    fn get_bb(&self) -> (Point<f64>, Point<f64>) {
+        let (min, max) = self.local().get_bb();
+        let mut world_min = Point { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY };
+        let mut world_max = Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY };
+        for corner in 0..8 {
+            let p = self.transform.point(Point {
+                x: if corner & 1 == 0 { min.x } else { max.x },
+                y: if corner & 2 == 0 { min.y } else { max.y },
+                z: if corner & 4 == 0 { min.z } else { max.z },
+            });
+            world_min = Point { x: world_min.x.min(p.x), y: world_min.y.min(p.y), z: world_min.z.min(p.z) };
+            world_max = Point { x: world_max.x.max(p.x), y: world_max.y.max(p.y), z: world_max.z.max(p.z) };
+        }
+        //The box holds the whole path while the shutter is open
+        let (end_min, end_max) = (world_min + self.velocity*self.shutter, world_max + self.velocity*self.shutter);
+        (Point { x: world_min.x.min(end_min.x), y: world_min.y.min(end_min.y), z: world_min.z.min(end_min.z) },
+        Point { x: world_max.x.max(end_max.x), y: world_max.y.max(end_max.y), z: world_max.z.max(end_max.z) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::sphere::Sphere;
+
+    #[test]
+    fn scaled_and_moved_sphere() {
+        let sphere = Arc::new(Surface::Sphere(Sphere::new(Point { x: 0., y: 0., z: 0. }, 1.)));
+        let transform = Transform::translate(Point { x: 0., y: 0., z: -5. }) * Transform::scale(Point { x: 1., y: 2., z: 1. });
+        let instance = Instance::new(sphere, transform);
+
+        //Hit the top of the ellipsoid from above
+        let ray = Ray::new(Point { x: 0., y: 10., z: -5. }, Point { x: 0., y: -2., z: 0. }, 0);
+        let hit = instance.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((ray.at(hit.t).y - 2.).abs() < 1e-9);
+        let record = instance.get_records(&ray, &hit);
+        assert!(record.front_face);
+        assert!((record.normal.y - 1.).abs() < 1e-9);
+
+        let (min, max) = instance.get_bb();
+        assert!((min.y + 2.).abs() < 1e-9 && (max.z + 4.).abs() < 1e-9);
+
+        //A long exposure sees it farther
+        let sphere = Arc::new(Surface::Sphere(Sphere::new(Point { x: 0., y: 0., z: 0. }, 1.)));
+        let moving = Instance::moving(sphere, Transform::identity(), Point { x: 3., y: 0., z: 0. }, 2.);
+        let (min, max) = moving.get_bb();
+        assert!((min.x + 1.).abs() < 1e-9 && (max.x - 7.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{point::Point, ray::Ray, color::Color};
+use super::{Hit, Hitable, Record, aabb::slab, triangle};
+
+const LEAF_SIZE : usize = 4;
+
+#[derive(Debug)]
+enum NodeKind {
+    Leaf { start : usize, count : usize },
+    Inner { left : usize, right : usize },
+}
+
+#[derive(Debug)]
+struct Node {
+    min : Point<f64>,
+    max : Point<f64>,
+    kind : NodeKind,
+}
+
+//A triangle mesh, with a bounding volume hierarchy over its triangles
+#[derive(Debug)]
+pub struct Mesh {
+    vertices : Vec<Point<f64>>,
+    normals : Option<Vec<Point<f64>>>, //Per vertex, used for smooth shading
+    colors : Option<Vec<Color<f64>>>, //Per vertex
+    uvs : Option<Vec<(f64, f64)>>, //Per vertex
+    triangles : Vec<[usize; 3]>,
+    nodes : Vec<Node>,
+}
+
+impl Mesh {
+    pub fn new(vertices : Vec<Point<f64>>, triangles : Vec<[usize; 3]>, normals : Option<Vec<Point<f64>>>, colors : Option<Vec<Color<f64>>>) -> Mesh {
+        assert!(!triangles.is_empty(), "A mesh needs at least one triangle");
+        assert!(triangles.iter().flatten().all(|&i| i < vertices.len()), "Triangle index out of the vertices");
+        assert!(normals.as_ref().is_none_or(|n| n.len() == vertices.len()), "There must be one normal per vertex");
+        assert!(colors.as_ref().is_none_or(|c| c.len() == vertices.len()), "There must be one color per vertex");
+
+        let mut mesh = Mesh {
+            vertices,
+            normals,
+            colors,
+            uvs : None,
+            triangles,
+            nodes : Vec::new(),
+        };
+        mesh.build(0, mesh.triangles.len());
+        mesh
+    }
+
+    pub fn set_uvs(&mut self, uvs : Vec<(f64, f64)>) {
+        assert_eq!(uvs.len(), self.vertices.len(), "There must be one texture coordinate per vertex");
+        self.uvs = Some(uvs);
+    }
+
+    pub fn vertices(&self) -> &[Point<f64>] {
+        &self.vertices
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    pub fn normals(&self) -> Option<&[Point<f64>]> {
+        self.normals.as_deref()
+    }
+
+    pub fn colors(&self) -> Option<&[Color<f64>]> {
+        self.colors.as_deref()
+    }
+
+    fn corners(&self, triangle : usize) -> (Point<f64>, Point<f64>, Point<f64>) {
+        let [a, b, c] = self.triangles[triangle];
+        (self.vertices[a], self.vertices[b], self.vertices[c])
+    }
+
+    fn bounds(&self, start : usize, count : usize) -> (Point<f64>, Point<f64>) {
+        let mut min = Point { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY };
+        let mut max = Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY };
+        for triangle in &self.triangles[start..start + count] {
+            for &i in triangle {
+                let p = self.vertices[i];
+                min = Point { x: min.x.min(p.x), y: min.y.min(p.y), z: min.z.min(p.z) };
+                max = Point { x: max.x.max(p.x), y: max.y.max(p.y), z: max.z.max(p.z) };
+            }
+        }
+        //Padding for flat boxes (triangles aligned with an axis)
+        let pad = Point { x: 1e-9, y: 1e-9, z: 1e-9 };
+        (min - pad, max + pad)
+    }
+
+    //Build the node of the triangles [start, start+count[ and return its index
+    //The triangles are split at the median of their centroids along the largest axis of the box
+    fn build(&mut self, start : usize, count : usize) -> usize {
+        let (min, max) = self.bounds(start, count);
+        let index = self.nodes.len();
+        self.nodes.push(Node { min, max, kind: NodeKind::Leaf { start, count } });
+        if count <= LEAF_SIZE {
+            return index;
+        }
+
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let vertices = &self.vertices;
+        let centroid = |triangle : &[usize; 3]| {
+            let c = vertices[triangle[0]] + vertices[triangle[1]] + vertices[triangle[2]];
+            match axis {
+                0 => c.x,
+                1 => c.y,
+                _ => c.z,
+            }
+        };
+        let half = count/2;
+        self.triangles[start..start + count].select_nth_unstable_by(half, |a, b| centroid(a).total_cmp(&centroid(b)));
+
+        let left = self.build(start, half);
+        let right = self.build(start + half, count - half);
+        self.nodes[index].kind = NodeKind::Inner { left, right };
+        index
+    }
+
+    //Find the closest triangle hit, return its t, index and barycentric coordinates
+    fn closest(&self, r : &Ray, t_min : f64, mut t_max : f64) -> Option<(f64, usize, f64, f64)> {
+        let mut best = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if slab(node.min, node.max, r, t_min, t_max).is_none() {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    for triangle in start..start + count {
+                        let (p0, p1, p2) = self.corners(triangle);
+                        if let Some((t, u, v)) = triangle::intersect(r, p0, p1, p2, t_min, t_max) {
+                            t_max = t;
+                            best = Some((t, triangle, u, v));
+                        }
+                    }
+                },
+                NodeKind::Inner { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                },
+            }
+        }
+        best
+    }
+}
+
+impl Hitable for Mesh {
+    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<Hit> {
+        self.closest(r, t_min, t_max).map(|(t, triangle, u, v)| Hit { t, primitive : triangle, coords : (u, v) })
+    }
+
+    fn get_records(&self, r : &Ray, hit : &Hit) -> Record {
+        let (t, triangle, (u, v)) = (hit.t, hit.primitive, hit.coords);
+        let (p0, p1, p2) = self.corners(triangle);
+
+        let [a, b, c] = self.triangles[triangle];
+
+        let outward_normal = match &self.normals {
+            Some(normals) => (normals[a]*(1. - u - v) + normals[b]*u + normals[c]*v).unit(),
+            None => ((p1 - p0)^(p2 - p0)).unit(),
+        };
+        let mut record = Record::new(r, t, r.at(t), outward_normal);
+        let w = 1. - u - v;
+        if let Some(colors) = &self.colors {
+            record.color = colors[a]*w + colors[b]*u + colors[c]*v;
+        }
+        if let Some(uvs) = &self.uvs {
+            record.uv = (uvs[a].0*w + uvs[b].0*u + uvs[c].0*v, uvs[a].1*w + uvs[b].1*u + uvs[c].1*v);
+        }
+        record
+    }
+
+    fn get_bb(&self) -> (Point<f64>, Point<f64>) {
+        (self.nodes[0].min, self.nodes[0].max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material, diffuse::Diffuse};
+
+    //A grid of n*n quads in the plane z = 0
+    fn grid(n : usize) -> Mesh {
+        let mut vertices = vec![];
+        for j in 0..=n {
+            for i in 0..=n {
+                vertices.push(Point { x: i as f64, y: j as f64, z: 0. });
+            }
+        }
+        let mut triangles = vec![];
+        for j in 0..n {
+            for i in 0..n {
+                let k = j*(n + 1) + i;
+                triangles.push([k, k + 1, k + n + 2]);
+                triangles.push([k, k + n + 2, k + n + 1]);
+            }
+        }
+        Mesh::new(vertices, triangles, None, None)
+    }
+
+    #[test]
+    fn bvh_finds_every_triangle() {
+        let mesh = grid(20);
+        for (x, y) in [(0.1, 0.2), (19.9, 19.95), (7.3, 12.8), (15.5, 0.5)] {
+            let ray = Ray::new(Point { x, y, z: 3. }, Point { x: 0., y: 0., z: -1. }, 0);
+            let hit = mesh.hit(&ray, 0.001, f64::MAX).expect("Should hit the grid");
+            assert!((hit.t - 3.).abs() < 1e-9);
+            let record = mesh.get_records(&ray, &hit);
+            assert!(record.front_face);
+            assert!((record.normal.z - 1.).abs() < 1e-9);
+        }
+
+        let outside = Ray::new(Point { x: 20.5, y: 3., z: 3. }, Point { x: 0., y: 0., z: -1. }, 0);
+        assert!(mesh.hit(&outside, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn vertex_colors_tint_the_albedo() {
+        let vertices = vec![Point { x: 0., y: 0., z: 0. }, Point { x: 1., y: 0., z: 0. }, Point { x: 0., y: 1., z: 0. }];
+        let colors = vec![Color { r: 1., g: 0., b: 0. }, Color { r: 0., g: 1., b: 0. }, Color { r: 0., g: 0., b: 1. }];
+        let mesh = Mesh::new(vertices, vec![[0, 1, 2]], None, Some(colors));
+
+        let ray = Ray::new(Point { x: 0.25, y: 0.5, z: 1. }, Point { x: 0., y: 0., z: -1. }, 0);
+        let record = mesh.get_records(&ray, &mesh.hit(&ray, 0.001, f64::MAX).unwrap());
+        assert!((record.color.r - 0.25).abs() < 1e-9 && (record.color.g - 0.25).abs() < 1e-9 && (record.color.b - 0.5).abs() < 1e-9);
+
+        let diffuse = Diffuse::new(Color { r: 0.5, g: 1., b: 1. });
+        assert!((diffuse.albedo(&record).r - 0.125).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{point::Point, ray::Ray};
+use super::{Hit, Hitable, Record, aabb::slab};
+
+const MAX_STEPS : u32 = 512;
+const EPSILON : f64 = 1e-4;
+
+//A signed distance function, built as a tree of primitives and operators
+//Every primitive is centered on the origin, use translate() to move it
+#[derive(Debug, Clone)]
+pub enum SdfNode {
+    Sphere { radius : f64 },
+    Box { half_size : Point<f64> },
+    Torus { major : f64, minor : f64 }, //Lying in the xz plane
+    Mandelbulb { power : f64, iterations : u32 },
+    Translate { offset : Point<f64>, node : Box<SdfNode> },
+    Union(Box<SdfNode>, Box<SdfNode>),
+    SmoothUnion { a : Box<SdfNode>, b : Box<SdfNode>, k : f64 },
+    Repeat { period : Point<f64>, node : Box<SdfNode> }, //A period of 0 on an axis disable the repetition on it
+    Twist { rate : f64, node : Box<SdfNode> }, //Around the y axis, in radians per unit
+    Displace { amplitude : f64, frequency : f64, node : Box<SdfNode> },
+}
+
+impl SdfNode {
+    pub fn sphere(radius : f64) -> SdfNode {
+        SdfNode::Sphere { radius }
+    }
+
+    pub fn cube(half_size : Point<f64>) -> SdfNode {
+        SdfNode::Box { half_size }
+    }
+
+    pub fn torus(major : f64, minor : f64) -> SdfNode {
+        SdfNode::Torus { major, minor }
+    }
+
+    pub fn mandelbulb(power : f64, iterations : u32) -> SdfNode {
+        SdfNode::Mandelbulb { power, iterations }
+    }
+
+    pub fn translate(self, offset : Point<f64>) -> SdfNode {
+        SdfNode::Translate { offset, node: Box::new(self) }
+    }
+
+    pub fn union(self, other : SdfNode) -> SdfNode {
+        SdfNode::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other : SdfNode, k : f64) -> SdfNode {
+        SdfNode::SmoothUnion { a: Box::new(self), b: Box::new(other), k }
+    }
+
+    pub fn repeat(self, period : Point<f64>) -> SdfNode {
+        SdfNode::Repeat { period, node: Box::new(self) }
+    }
+
+    pub fn twist(self, rate : f64) -> SdfNode {
+        SdfNode::Twist { rate, node: Box::new(self) }
+    }
+
+    pub fn displace(self, amplitude : f64, frequency : f64) -> SdfNode {
+        SdfNode::Displace { amplitude, frequency, node: Box::new(self) }
+    }
+
+    pub fn distance(&self, p : Point<f64>) -> f64 {
+        match self {
+            SdfNode::Sphere { radius } => p.norm() - radius,
+            SdfNode::Box { half_size } => {
+                let q = Point { x: p.x.abs() - half_size.x, y: p.y.abs() - half_size.y, z: p.z.abs() - half_size.z };
+                let outside = Point { x: q.x.max(0.), y: q.y.max(0.), z: q.z.max(0.) };
+                outside.norm() + q.x.max(q.y).max(q.z).min(0.)
+            },
+            SdfNode::Torus { major, minor } => {
+                let qx = (p.x*p.x + p.z*p.z).sqrt() - major;
+                (qx*qx + p.y*p.y).sqrt() - minor
+            },
+            SdfNode::Mandelbulb { power, iterations } => mandelbulb(p, *power, *iterations),
+            SdfNode::Translate { offset, node } => node.distance(p - *offset),
+            SdfNode::Union(a, b) => a.distance(p).min(b.distance(p)),
+            SdfNode::SmoothUnion { a, b, k } => {
+                //Polynomial smooth minimum
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5*(db - da)/k).clamp(0., 1.);
+                db*(1. - h) + da*h - k*h*(1. - h)
+            },
+            SdfNode::Repeat { period, node } => {
+                let modulo = |x : f64, period : f64| if period > 0. { x - period*(x/period).round() } else { x };
+                node.distance(Point { x: modulo(p.x, period.x), y: modulo(p.y, period.y), z: modulo(p.z, period.z) })
+            },
+            SdfNode::Twist { rate, node } => {
+                let (sin, cos) = (rate*p.y).sin_cos();
+                node.distance(Point { x: cos*p.x - sin*p.z, y: p.y, z: sin*p.x + cos*p.z })
+            },
+            SdfNode::Displace { amplitude, frequency, node } => {
+                node.distance(p) + amplitude*(frequency*p.x).sin()*(frequency*p.y).sin()*(frequency*p.z).sin()
+            },
+        }
+    }
+}
+
+//Distance estimator of the power n Mandelbulb
+fn mandelbulb(p : Point<f64>, power : f64, iterations : u32) -> f64 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+    for _ in 0..iterations {
+        r = z.norm();
+        if !(1e-12..=2.0).contains(&r) {
+            break;
+        }
+        let theta = (z.z/r).acos()*power;
+        let phi = z.y.atan2(z.x)*power;
+        dr = r.powf(power - 1.)*power*dr + 1.0;
+        z = Point { x: theta.sin()*phi.cos(), y: theta.sin()*phi.sin(), z: theta.cos() }*r.powf(power) + p;
+    }
+    if r < 1e-12 {
+        return 0.;
+    }
+    0.5*r.ln()*r/dr
+}
+
+//A surface defined by a distance function, intersected by sphere tracing inside its bounding box
+#[derive(Debug)]
+pub struct Sdf {
+    root : SdfNode,
+    min : Point<f64>,
+    max : Point<f64>,
+    //Scale applied to each step, must be lower than 1 when the function overestimates the distance (twist, displacement)
+    step_scale : f64,
+}
+
+impl Sdf {
+    pub fn new(root : SdfNode, min : Point<f64>, max : Point<f64>, step_scale : f64) -> Sdf {
+        Sdf {
+            root,
+            min,
+            max,
+            step_scale,
+        }
+    }
+
+    pub fn distance(&self, p : Point<f64>) -> f64 {
+        self.root.distance(p)
+    }
+
+    //Estimate the normal with the gradient of the distance (central differences)
+    fn gradient(&self, p : Point<f64>) -> Point<f64> {
+        let h = EPSILON;
+        let dx = Point { x: h, y: 0., z: 0. };
+        let dy = Point { x: 0., y: h, z: 0. };
+        let dz = Point { x: 0., y: 0., z: h };
+        Point {
+            x: self.distance(p + dx) - self.distance(p - dx),
+            y: self.distance(p + dy) - self.distance(p - dy),
+            z: self.distance(p + dz) - self.distance(p - dz),
+        }.unit()
+    }
+}
+
+impl Hitable for Sdf {
+    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<Hit> {
+        let (t0, t1) = slab(self.min, self.max, r, t_min, t_max)?;
+        let dir_norm = r.dir().norm();
+
+        //A ray leaving the surface (scattered ray) must first get away from it before being able to hit
+        let mut t = t0;
+        let mut escaping = self.distance(r.at(t)).abs() < 2.*EPSILON;
+
+        for _ in 0..MAX_STEPS {
+            let d = self.distance(r.at(t)).abs()*self.step_scale;
+            if escaping {
+                escaping = d < 2.*EPSILON;
+                t += d.max(EPSILON)/dir_norm;
+            }
+            else if d < EPSILON {
+                return Some(Hit::new(t));
+            }
+            else {
+                t += d/dir_norm;
+            }
+
+            if t > t1 {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn get_records(&self, r : &Ray, hit : &Hit) -> Record {
+        let p = r.at(hit.t);
+        Record::new(r, hit.t, p, self.gradient(p))
+    }
+
+    fn get_bb(&self) -> (Point<f64>, Point<f64>) {
+        (self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::sphere::Sphere;
+
+    #[test]
+    fn sphere_tracing_matches_sphere() {
+        let one = Point { x: 1., y: 1., z: 1. };
+        let sdf = Sdf::new(SdfNode::sphere(1.), one*(-1.), one, 1.);
+        let sphere = Sphere::new(Point { x: 0., y: 0., z: 0. }, 1.);
+        let ray = Ray::new(Point { x: 0.3, y: 0.2, z: 5. }, Point { x: 0., y: 0., z: -2. }, 0);
+
+        let hit_sdf = sdf.hit(&ray, 0.001, f64::MAX).unwrap();
+        let hit_sphere = sphere.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((hit_sdf.t - hit_sphere.t).abs() < 1e-3);
+
+        let normal = sdf.get_records(&ray, &hit_sdf).normal;
+        let expected = sphere.get_records(&ray, &hit_sphere).normal;
+        assert!((normal - expected).norm() < 1e-3);
+    }
+
+    #[test]
+    fn smooth_union_is_below_union() {
+        let a = SdfNode::sphere(1.);
+        let b = SdfNode::sphere(1.).translate(Point { x: 1.5, y: 0., z: 0. });
+        let p = Point { x: 0.75, y: 1.0, z: 0. };
+        let hard = a.clone().union(b.clone()).distance(p);
+        let smooth = a.smooth_union(b, 0.5).distance(p);
+        assert!(smooth < hard);
+    }
+
+    #[test]
+    fn miss_outside_bounding_box() {
+        let one = Point { x: 1., y: 1., z: 1. };
+        let sdf = Sdf::new(SdfNode::sphere(1.).repeat(one*3.), one*(-1.), one, 1.);
+        let ray = Ray::new(Point { x: 3., y: 0., z: 5. }, Point { x: 0., y: 0., z: -1. }, 0);
+        assert!(sdf.hit(&ray, 0.001, f64::MAX).is_none());
+    }
+}
@@ -3,8 +3,9 @@
 use std::sync::Arc;
 
 use crate::{point::Point, ray::Ray, material::Texture};
-use super::{Hitable, Record, aabb::Aabb};
+use super::{Hit, Hitable, Record, aabb::Aabb};
 
+#[derive(Debug)]
 pub struct Sphere {
     center : Point<f64>,
     radius : f64
@@ -12,10 +13,10 @@ pub struct Sphere {
 
 impl Sphere {
     pub fn new(center : Point<f64>, radius : f64) -> Sphere {
-        return Sphere {
+        Sphere {
             center,
             radius
-        };
+        }
     }
 
     pub fn new_with_aabb(center : Point<f64>, radius : f64, texture : Arc<Texture>) -> Aabb {
@@ -32,11 +33,11 @@ impl Sphere {
 }
 
 impl Hitable for  Sphere {
-    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<f64> {
+    fn hit(&self, r : &Ray, t_min : f64, t_max : f64) -> Option<Hit> {
         let oc = *(r.orig())-self.center;
-        let a : f64 = *(r.dir())&*(r.dir());
+        let a : f64 = r.dir().norm_squared();
         let half_b : f64 = *(r.dir())&(oc);
-        let c : f64 = ((oc)&(oc))-(self.radius*self.radius);
+        let c : f64 = oc.norm_squared()-(self.radius*self.radius);
         let delta : f64 = half_b*half_b - a*c;
         if delta<0.0 {
             return None;
@@ -47,16 +48,17 @@ impl Hitable for  Sphere {
         let t1 = (-half_b - sqrt_delta)/a;
         let t2 = (-half_b + sqrt_delta)/a;
         if t1 >= t_min && t1 <= t_max {
-            Some(t1)
+            Some(Hit::new(t1))
         }
         else if t2>=t_min && t2<=t_max {
-            Some(t2)
+            Some(Hit::new(t2))
         }
         else{
-            return None;
+            None
         }
     }
-    fn get_records(&self, r : &Ray, t : f64) -> Record {
+    fn get_records(&self, r : &Ray, hit : &Hit) -> Record {
+        let t = hit.t;
         let p = r.at(t);
         let outward_normal = (p-self.center)/self.radius;
 
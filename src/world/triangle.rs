@@ -0,0 +1,33 @@
+use crate::{point::Point, ray::Ray};
+
+//Möller–Trumbore ray/triangle intersection
+//Return the t of the hit and the barycentric coordinates (u, v) of the hit point relative to p1 and p2
+pub fn intersect(r : &Ray, p0 : Point<f64>, p1 : Point<f64>, p2 : Point<f64>, t_min : f64, t_max : f64) -> Option<(f64, f64, f64)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let h = *(r.dir())^edge2;
+    let det = edge1&h;
+    if det.abs() < 1e-12 {
+        //The ray is parallel to the triangle
+        return None;
+    }
+
+    let inv_det = 1./det;
+    let s = *(r.orig()) - p0;
+    let u = inv_det*(s&h);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s^edge1;
+    let v = inv_det*(*(r.dir())&q);
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = inv_det*(edge2&q);
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, u, v))
+}